@@ -0,0 +1,420 @@
+//! Generates typed bindings (structs, selectors, call builders, view functions and events) from
+//! the contract ABIs in `abi/`, included by `src/bindings.rs`.
+
+use serde_json::Value;
+use starknet_core::utils::get_selector_from_name;
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::Path};
+
+const CONTRACTS: &[&str] = &["bitvm_bridge", "btc_light_client"];
+
+fn main() {
+    let out_dir = env::var("OUT_DIR").unwrap();
+    for contract in CONTRACTS {
+        let path = format!("abi/{contract}.json");
+        println!("cargo:rerun-if-changed={path}");
+        let abi: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("Invalid ABI {path}: {e}"));
+        let code = Generator::new(&abi).generate();
+        fs::write(Path::new(&out_dir).join(format!("{contract}.rs")), code).unwrap();
+    }
+}
+
+struct Generator<'a> {
+    entries: Vec<&'a Value>,
+    // Rust names of the ABI's own structs and enums by Cairo path
+    types: BTreeMap<&'a str, String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(abi: &'a Value) -> Self {
+        let mut entries = vec![];
+        for entry in abi.as_array().expect("ABI must be an array") {
+            match entry["type"].as_str() {
+                Some("interface") => entries.extend(entry["items"].as_array().unwrap()),
+                _ => entries.push(entry),
+            }
+        }
+
+        let mut types = BTreeMap::new();
+        for entry in &entries {
+            let name = entry["name"].as_str().unwrap_or_default();
+            if matches!(entry["type"].as_str(), Some("struct" | "enum"))
+                && !name.starts_with("core::")
+            {
+                let rust_name = name.rsplit("::").next().unwrap().to_string();
+                assert!(
+                    !types.values().any(|n| *n == rust_name),
+                    "Two ABI types are named {rust_name}"
+                );
+                types.insert(name, rust_name);
+            }
+        }
+        Self { entries, types }
+    }
+
+    fn generate(&self) -> String {
+        let mut code = String::from(
+            "#[allow(unused_imports)]\nuse starknet::{\n    \
+                core::{\n        \
+                    codec::{Decode, Encode},\n        \
+                    types::{BlockId, ByteArray, Call, Felt, FunctionCall, U256},\n    \
+                },\n    \
+                providers::Provider,\n\
+            };\n",
+        );
+        for entry in &self.entries {
+            match entry["type"].as_str() {
+                Some("struct") => self.generate_struct(entry, &mut code),
+                Some("enum") => self.generate_enum(entry, &mut code),
+                Some("function") => self.generate_function(entry, &mut code),
+                _ => {}
+            }
+        }
+        self.generate_events(&mut code);
+        code
+    }
+
+    fn generate_struct(&self, entry: &Value, code: &mut String) {
+        let Some(name) = self.types.get(entry["name"].as_str().unwrap()) else {
+            return;
+        };
+        writeln!(
+            code,
+            "\n#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]"
+        )
+        .unwrap();
+        writeln!(code, "pub struct {name} {{").unwrap();
+        for member in members(entry, "members") {
+            let ty = self.rust_type(member["type"].as_str().unwrap());
+            writeln!(code, "    pub {}: {ty},", ident(member)).unwrap();
+        }
+        code.push_str("}\n");
+    }
+
+    fn generate_enum(&self, entry: &Value, code: &mut String) {
+        let Some(name) = self.types.get(entry["name"].as_str().unwrap()) else {
+            return;
+        };
+        writeln!(
+            code,
+            "\n#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]"
+        )
+        .unwrap();
+        writeln!(code, "pub enum {name} {{").unwrap();
+        for variant in members(entry, "variants") {
+            let variant_name = variant["name"].as_str().unwrap();
+            match variant["type"].as_str().unwrap() {
+                "()" => writeln!(code, "    {variant_name},").unwrap(),
+                ty => writeln!(code, "    {variant_name}({}),", self.rust_type(ty)).unwrap(),
+            }
+        }
+        code.push_str("}\n");
+    }
+
+    /// A selector constant, plus a [`Call`] builder for external functions or an async call
+    /// decoding the output for view functions.
+    fn generate_function(&self, entry: &Value, code: &mut String) {
+        let name = entry["name"].as_str().unwrap();
+        let selector_name = format!("{}_SELECTOR", name.to_uppercase());
+        writeln!(
+            code,
+            "\npub const {selector_name}: Felt = {};",
+            selector(name)
+        )
+        .unwrap();
+
+        let inputs = members(entry, "inputs");
+        let mut params = String::new();
+        let mut encode = String::new();
+        for input in inputs {
+            let ty = self.rust_type(input["type"].as_str().unwrap());
+            write!(params, ", {}: {}", ident(input), param_type(&ty)).unwrap();
+            writeln!(encode, "    {}.encode(&mut calldata)?;", ident(input)).unwrap();
+        }
+        let calldata = if inputs.is_empty() {
+            "    let calldata = vec![];\n".to_string()
+        } else {
+            format!("    let mut calldata: Vec<Felt> = vec![];\n{encode}")
+        };
+
+        if entry["state_mutability"] == "view" {
+            let outputs: Vec<String> = members(entry, "outputs")
+                .iter()
+                .map(|output| self.rust_type(output["type"].as_str().unwrap()))
+                .collect();
+            let (output_type, decode) = match outputs.as_slice() {
+                [] => ("()".to_string(), "Ok(())".to_string()),
+                [ty] => (
+                    ty.clone(),
+                    "Ok(Decode::decode_iter(&mut output)?)".to_string(),
+                ),
+                tys => (
+                    format!("({})", tys.join(", ")),
+                    format!(
+                        "Ok(({}))",
+                        vec!["Decode::decode_iter(&mut output)?"; tys.len()].join(", ")
+                    ),
+                ),
+            };
+            let output_binding = if outputs.is_empty() {
+                "_output"
+            } else {
+                "output"
+            };
+            write!(
+                code,
+                "\npub async fn {name}<P: Provider + Sync>(\n    provider: &P,\n    contract: Felt{params},\n    block_id: BlockId,\n) -> anyhow::Result<{output_type}> {{\n\
+                 {calldata}\
+                 \x20   let {output_binding} = provider\n\
+                 \x20       .call(\n\
+                 \x20           FunctionCall {{\n\
+                 \x20               contract_address: contract,\n\
+                 \x20               entry_point_selector: {selector_name},\n\
+                 \x20               calldata,\n\
+                 \x20           }},\n\
+                 \x20           block_id,\n\
+                 \x20       )\n\
+                 \x20       .await?;\n"
+            )
+            .unwrap();
+            if !outputs.is_empty() {
+                code.push_str("    let mut output = output.iter();\n");
+            }
+            writeln!(code, "    {decode}\n}}").unwrap();
+        } else {
+            write!(
+                code,
+                "\npub fn {name}(contract: Felt{params}) -> anyhow::Result<Call> {{\n\
+                 {calldata}\
+                 \x20   Ok(Call {{\n\
+                 \x20       to: contract,\n\
+                 \x20       selector: {selector_name},\n\
+                 \x20       calldata,\n\
+                 \x20   }})\n\
+                 }}\n"
+            )
+            .unwrap();
+        }
+    }
+
+    /// A struct per event with its selector and decoding from keys and data, and an `Event` enum
+    /// over all of them, if the contract has any. Only struct events nested in the contract's event
+    /// enum are supported.
+    fn generate_events(&self, code: &mut String) {
+        let events: BTreeMap<&str, &Value> = self
+            .entries
+            .iter()
+            .filter(|e| e["type"] == "event")
+            .map(|e| (e["name"].as_str().unwrap(), *e))
+            .collect();
+        let nested: Vec<&str> = events
+            .values()
+            .flat_map(|e| members(e, "variants"))
+            .map(|v| v["type"].as_str().unwrap())
+            .collect();
+        let variants: Vec<&Value> = events
+            .values()
+            .filter(|e| e["kind"] == "enum" && !nested.contains(&e["name"].as_str().unwrap()))
+            .flat_map(|e| members(e, "variants"))
+            .collect();
+        if variants.is_empty() {
+            return;
+        }
+
+        code.push_str("\npub mod events {\n    use super::*;\n");
+        let mut names = vec![];
+        for variant in variants {
+            let name = variant["name"].as_str().unwrap();
+            let event = events[variant["type"].as_str().unwrap()];
+            assert!(
+                variant["kind"] == "nested" && event["kind"] == "struct",
+                "Event {name} is not a nested struct event"
+            );
+            names.push(name);
+
+            let mut fields = String::new();
+            let mut decode = String::new();
+            for member in members(event, "members") {
+                let ty = self.rust_type(member["type"].as_str().unwrap());
+                let source = match member["kind"].as_str() {
+                    Some("key") => "keys",
+                    Some("data") => "data",
+                    kind => panic!("Unsupported member kind {kind:?} of event {name}"),
+                };
+                writeln!(fields, "        pub {}: {ty},", ident(member)).unwrap();
+                writeln!(
+                    decode,
+                    "                {}: Decode::decode_iter(&mut {source})?,",
+                    ident(member)
+                )
+                .unwrap();
+            }
+            write!(
+                code,
+                "\n    #[derive(Debug, Clone, PartialEq, Eq)]\n    pub struct {name} {{\n{fields}    }}\n\n\
+                 \x20   impl {name} {{\n\
+                 \x20       pub const SELECTOR: Felt = {};\n\n\
+                 \x20       /// Decodes the event from its keys, selector first, and data.\n\
+                 \x20       pub fn decode(keys: &[Felt], data: &[Felt]) -> anyhow::Result<Self> {{\n\
+                 \x20           let (selector, keys) = keys\n\
+                 \x20               .split_first()\n\
+                 \x20               .ok_or_else(|| anyhow::anyhow!(\"Event without keys\"))?;\n\
+                 \x20           if *selector != Self::SELECTOR {{\n\
+                 \x20               anyhow::bail!(\"Not a {name} event\");\n\
+                 \x20           }}\n\
+                 \x20           let (mut keys, mut data) = (keys.iter(), data.iter());\n\
+                 \x20           let event = Self {{\n{decode}            }};\n\
+                 \x20           if keys.next().is_some() || data.next().is_some() {{\n\
+                 \x20               anyhow::bail!(\"{name} event has more keys or data than its ABI\");\n\
+                 \x20           }}\n\
+                 \x20           Ok(event)\n\
+                 \x20       }}\n\
+                 \x20   }}\n",
+                selector(name)
+            )
+            .unwrap();
+        }
+
+        code.push_str("\n    #[derive(Debug, Clone, PartialEq, Eq)]\n    pub enum Event {\n");
+        for name in &names {
+            writeln!(code, "        {name}({name}),").unwrap();
+        }
+        writeln!(
+            code,
+            "    }}\n\n    impl Event {{\n        pub const SELECTORS: [Felt; {}] = [{}];\n",
+            names.len(),
+            names
+                .iter()
+                .map(|name| format!("{name}::SELECTOR"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .unwrap();
+        code.push_str(
+            "        pub fn decode(keys: &[Felt], data: &[Felt]) -> anyhow::Result<Self> {\n            \
+                match keys.first() {\n",
+        );
+        for name in &names {
+            writeln!(
+                code,
+                "                Some(selector) if *selector == {name}::SELECTOR => {{\n                    \
+                    Ok(Self::{name}({name}::decode(keys, data)?))\n                }}"
+            )
+            .unwrap();
+        }
+        code.push_str(
+            "                _ => anyhow::bail!(\"Unknown event\"),\n            }\n        }\n    }\n}\n",
+        );
+    }
+
+    fn rust_type(&self, ty: &str) -> String {
+        match ty {
+            "core::felt252"
+            | "core::bytes_31::bytes31"
+            | "core::starknet::contract_address::ContractAddress"
+            | "core::starknet::class_hash::ClassHash"
+            | "core::starknet::eth_address::EthAddress"
+            | "core::starknet::storage_access::StorageAddress" => "Felt".to_string(),
+            "core::bool" => "bool".to_string(),
+            "core::integer::u8" => "u8".to_string(),
+            "core::integer::u16" => "u16".to_string(),
+            "core::integer::u32" => "u32".to_string(),
+            "core::integer::u64" => "u64".to_string(),
+            "core::integer::u128" => "u128".to_string(),
+            "core::integer::u256" => "U256".to_string(),
+            "core::integer::i8" => "crate::bindings::I8".to_string(),
+            "core::integer::i16" => "crate::bindings::I16".to_string(),
+            "core::integer::i32" => "crate::bindings::I32".to_string(),
+            "core::integer::i64" => "crate::bindings::I64".to_string(),
+            "core::integer::i128" => "i128".to_string(),
+            "core::byte_array::ByteArray" => "ByteArray".to_string(),
+            _ if ty.starts_with('(') && ty.ends_with(')') => {
+                let elements = split_types(&ty[1..ty.len() - 1]);
+                assert!(
+                    (1..=8).contains(&elements.len()),
+                    "Unsupported ABI tuple {ty}"
+                );
+                let elements: Vec<String> = elements.iter().map(|e| self.rust_type(e)).collect();
+                format!(
+                    "crate::bindings::Tuple{}<{}>",
+                    elements.len(),
+                    elements.join(", ")
+                )
+            }
+            _ => {
+                if let Some(name) = self.types.get(ty) {
+                    return name.clone();
+                }
+                let (base, arg) = ty
+                    .split_once("::<")
+                    .and_then(|(base, arg)| Some((base, arg.strip_suffix('>')?)))
+                    .unwrap_or_else(|| panic!("Unsupported ABI type {ty}"));
+                match base {
+                    "core::array::Array" | "core::array::Span" => {
+                        format!("Vec<{}>", self.rust_type(arg))
+                    }
+                    "core::option::Option" => format!("Option<{}>", self.rust_type(arg)),
+                    "core::zeroable::NonZero" => self.rust_type(arg),
+                    _ => panic!("Unsupported ABI type {ty}"),
+                }
+            }
+        }
+    }
+}
+
+fn members<'v>(entry: &'v Value, key: &str) -> &'v [Value] {
+    entry[key].as_array().map_or(&[], Vec::as_slice)
+}
+
+/// Splits a comma separated list of types at the top nesting level.
+fn split_types(types: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in types.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(types[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = types[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+fn selector(name: &str) -> String {
+    let selector = get_selector_from_name(name).unwrap();
+    format!("Felt::from_hex_unchecked(\"{selector:#x}\")")
+}
+
+fn ident(member: &Value) -> String {
+    let name = member["name"].as_str().unwrap();
+    match name {
+        "as" | "break" | "const" | "continue" | "crate" | "else" | "enum" | "extern" | "false"
+        | "fn" | "for" | "if" | "impl" | "in" | "let" | "loop" | "match" | "mod" | "move"
+        | "mut" | "pub" | "ref" | "return" | "static" | "struct" | "trait" | "true" | "type"
+        | "unsafe" | "use" | "where" | "while" | "async" | "await" | "dyn" | "gen" | "try" => {
+            format!("r#{name}")
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Copy types are passed by value, the others by reference.
+fn param_type(ty: &str) -> String {
+    match ty {
+        "Felt" | "bool" | "u8" | "u16" | "u32" | "u64" | "u128" | "U256" | "i128" => ty.to_string(),
+        // The narrower signed integers
+        _ if ty.starts_with("crate::bindings::I") => ty.to_string(),
+        _ => match ty.strip_prefix("Vec<") {
+            Some(element) => format!("&[{}]", element.strip_suffix('>').unwrap()),
+            None => format!("&{ty}"),
+        },
+    }
+}
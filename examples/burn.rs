@@ -21,5 +21,5 @@ async fn main() {
         .await
         .unwrap();
 
-    println!("tx_hash: {}", tx_hash);
+    println!("tx_hash: {tx_hash}");
 }
@@ -1,49 +1,43 @@
 use async_trait::async_trait;
-use starknet_client_sdk::events::{EventHandler, EventMonitor};
+use starknet_client_sdk::{
+    checkpoint::FileCheckpointStore,
+    events::{BurnEvent, EventHandler, EventMeta, EventMonitor, MintEvent},
+};
 
 struct MyEventHandler;
 
 #[async_trait]
 impl EventHandler for MyEventHandler {
-    async fn handle_mint(
-        &self,
-        block_number: u64,
-        block_timestamp: u64,
-        tx_hash: &str,
-        to: &str,
-        value: u64,
-    ) -> anyhow::Result<()> {
+    async fn handle_mint(&self, event: &MintEvent) -> anyhow::Result<()> {
         println!("Mint event detected:");
-        println!("  Block number: {}", block_number);
-        println!("  Block Timestamp: {}", block_timestamp);
-        println!("  Transaction hash: {}", tx_hash);
-        println!("  To: {}", to);
-        println!("  Amount: {}", value);
+        print_meta(&event.meta);
+        println!("  To: {}", event.to);
+        println!("  Amount: {}", event.value);
         Ok(())
     }
 
-    async fn handle_burn(
-        &self,
-        block_number: u64,
-        block_timestamp: u64,
-        tx_hash: &str,
-        from: &str,
-        btc_addr: &str,
-        value: u64,
-        fee_rate: u64,
-        operator_id: u64,
-    ) -> anyhow::Result<()> {
+    async fn handle_burn(&self, event: &BurnEvent) -> anyhow::Result<()> {
         println!("Burn event detected:");
-        println!("  Block number: {}", block_number);
-        println!("  Block Timestamp: {}", block_timestamp);
-        println!("  Transaction hash: {}", tx_hash);
-        println!("  From: {}", from);
-        println!("  BTC Address: {}", btc_addr);
-        println!("  Amount: {}", value);
-        println!("  Fee rate: {}", fee_rate);
-        println!("  Operator ID: {}", operator_id);
+        print_meta(&event.meta);
+        println!("  From: {}", event.from);
+        println!("  BTC Address: {}", event.btc_addr);
+        println!("  Amount: {}", event.value);
+        println!("  Fee rate: {}", event.fee_rate);
+        println!("  Operator ID: {}", event.operator_id);
         Ok(())
     }
+
+    async fn on_reorg(&self, from_height: u64) -> anyhow::Result<()> {
+        println!("Chain reorganized, blocks from {from_height} are delivered again");
+        Ok(())
+    }
+}
+
+fn print_meta(meta: &EventMeta) {
+    println!("  Event id: {}", meta.id());
+    println!("  Block number: {}", meta.block_number);
+    println!("  Block Timestamp: {}", meta.block_timestamp);
+    println!("  Transaction hash: {}", meta.tx_hash_hex());
 }
 
 #[tokio::main]
@@ -56,43 +50,36 @@ async fn main() -> anyhow::Result<()> {
     let handler = Box::new(MyEventHandler);
 
     // Create and start event monitor
-    let mut monitor = EventMonitor::new(bitvm_bridge_contract_address, url, handler, 890869);
-
-    println!("Starting event monitor...");
+    // Resume from the last handled block across restarts
+    let mut monitor = EventMonitor::new(bitvm_bridge_contract_address, url, handler, 890869)
+        .with_checkpoint_store(Box::new(FileCheckpointStore::new(
+            "event_listener.checkpoint",
+        )));
 
-    loop {
-        let initial_processed_height = monitor.processed_height();
+    println!(
+        "Starting event monitor (RPC spec {})...",
+        monitor.spec_version().await?
+    );
 
-        match monitor.process().await {
-            Ok(()) => {
-                let latest_block_number = monitor.latest_block_number().await?;
-                let processed_height = monitor.processed_height();
+    // Catches up on history with concurrent fetches before following the chain
+    let final_height = monitor.final_height().await?;
+    monitor
+        .backfill(final_height, |progress| {
+            println!(
+                "Backfilled to block {} ({:.0}%)",
+                progress.processed_height,
+                progress.fraction() * 100.0
+            );
+        })
+        .await?;
 
-                println!(
-                    "latest_block_number: {}, processed_height: {}",
-                    latest_block_number, processed_height
-                );
+    // Runs until Ctrl-C, finishing the batch in progress before exiting
+    monitor
+        .run(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
 
-                // Check if we've caught up with the latest blocks
-                if latest_block_number <= processed_height + 6 {
-                    // 6 is CONFIRMED_BLOCKS
-                    println!("Caught up with latest blocks. Monitoring for new blocks...");
-
-                    // If no progress was made, sleep longer to avoid busy waiting
-                    if processed_height == initial_processed_height {
-                        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
-                    } else {
-                        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-                    }
-                } else {
-                    // Still catching up, process immediately
-                    println!("Still catching up, processing next batch...");
-                }
-            }
-            Err(e) => {
-                eprintln!("Error processing events: {}", e);
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-            }
-        }
-    }
+    println!("Stopped at block {}", monitor.processed_height());
+    Ok(())
 }
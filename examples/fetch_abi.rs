@@ -0,0 +1,40 @@
+//! Fetches the ABI of a deployed contract's class, to replace a file in `abi/`:
+//!
+//! cargo run --example fetch_abi -- <rpc url> <contract address> <out>
+
+use serde_json::{Value, json};
+use starknet::providers::jsonrpc::{JsonRpcMethod, JsonRpcResponse, JsonRpcTransport};
+use starknet_client_sdk::query_client::QueryClient;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [url, contract, out] = args.as_slice() else {
+        anyhow::bail!("Usage: fetch_abi <rpc url> <contract address> <out>");
+    };
+    let client = QueryClient::connect(url).await?;
+
+    let class = match client
+        .transport()
+        .send_request::<Value, Value>(
+            JsonRpcMethod::GetClassAt,
+            json!({ "block_id": "latest", "contract_address": contract }),
+        )
+        .await?
+    {
+        JsonRpcResponse::Success { result, .. } => result,
+        JsonRpcResponse::Error { error, .. } => {
+            anyhow::bail!("starknet_getClassAt failed: {error}")
+        }
+    };
+    // Sierra classes carry their ABI as a JSON string, legacy classes as JSON
+    let abi: Value = match &class["abi"] {
+        Value::String(abi) => serde_json::from_str(abi)?,
+        Value::Null => anyhow::bail!("The class of {contract} has no ABI"),
+        abi => abi.clone(),
+    };
+
+    std::fs::write(out, serde_json::to_string_pretty(&abi)? + "\n")?;
+    println!("Wrote the ABI of {contract} to {out}");
+    Ok(())
+}
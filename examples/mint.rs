@@ -79,5 +79,5 @@ async fn main() {
         .unwrap();
 
     let balance = felt_to_u64(balance.first().unwrap()).unwrap();
-    println!("Balance: {:?}", balance);
+    println!("Balance: {balance:?}");
 }
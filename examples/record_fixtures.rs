@@ -0,0 +1,123 @@
+//! Records the responses `tests/fixtures/rpc_v0_*.json` are made of from a live node:
+//!
+//! cargo run --example record_fixtures -- <rpc url> <bridge contract> <block> <reverted tx> <out>
+//!
+//! `block` must hold at least one Mint and one Burn of the bridge, the first of them in a
+//! successful transaction; `reverted tx` is any reverted invoke transaction.
+
+use serde_json::{Value, json};
+use starknet::providers::jsonrpc::{JsonRpcMethod, JsonRpcResponse, JsonRpcTransport};
+use starknet_client_sdk::{
+    query_client::QueryClient,
+    spec::SpecVersion,
+    types::{BURN_EVENT_SELECTOR, MINT_EVENT_SELECTOR},
+};
+
+async fn request(
+    client: &QueryClient,
+    method: JsonRpcMethod,
+    params: Value,
+) -> anyhow::Result<Value> {
+    match client
+        .transport()
+        .send_request::<Value, Value>(method, params)
+        .await?
+    {
+        JsonRpcResponse::Success { result, .. } => Ok(result),
+        JsonRpcResponse::Error { error, .. } => anyhow::bail!("{method:?} failed: {error}"),
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [url, bridge, block, reverted_tx, out] = args.as_slice() else {
+        anyhow::bail!(
+            "Usage: record_fixtures <rpc url> <bridge contract> <block> <reverted tx> <out>"
+        );
+    };
+    let block: u64 = block.parse()?;
+    let client = QueryClient::connect(url).await?;
+    let version = client.spec_version().await?;
+
+    let spec_version = request(&client, JsonRpcMethod::SpecVersion, json!([])).await?;
+    let events = request(
+        &client,
+        JsonRpcMethod::GetEvents,
+        json!({ "filter": {
+            "from_block": { "block_number": block },
+            "to_block": { "block_number": block },
+            "address": bridge,
+            "keys": [[
+                format!("{MINT_EVENT_SELECTOR:#x}"),
+                format!("{BURN_EVENT_SELECTOR:#x}"),
+            ]],
+            "chunk_size": 2,
+        }}),
+    )
+    .await?;
+    let tx_hash = &events["events"][0]["transaction_hash"];
+    let pending_block_id = if version >= SpecVersion::V0_9 {
+        "pre_confirmed"
+    } else {
+        "pending"
+    };
+    let pending_block = request(
+        &client,
+        JsonRpcMethod::GetBlockWithTxHashes,
+        json!({ "block_id": pending_block_id }),
+    )
+    .await?;
+
+    let mut fixture = json!({
+        "spec_version": spec_version,
+        "transaction_status": request(
+            &client,
+            JsonRpcMethod::GetTransactionStatus,
+            json!({ "transaction_hash": tx_hash }),
+        )
+        .await?,
+        "transaction_receipt": request(
+            &client,
+            JsonRpcMethod::GetTransactionReceipt,
+            json!({ "transaction_hash": tx_hash }),
+        )
+        .await?,
+        "reverted_receipt": request(
+            &client,
+            JsonRpcMethod::GetTransactionReceipt,
+            json!({ "transaction_hash": reverted_tx }),
+        )
+        .await?,
+        "events": events,
+        "block": request(
+            &client,
+            JsonRpcMethod::GetBlockWithTxHashes,
+            json!({ "block_id": { "block_number": block } }),
+        )
+        .await?,
+        "pending_block": pending_block,
+    });
+
+    // A transaction of the pre-confirmed block, while it still is pre-confirmed
+    if version >= SpecVersion::V0_9
+        && let Some(pre_confirmed_tx) = fixture["pending_block"]["transactions"].get(0).cloned()
+    {
+        fixture["pre_confirmed_status"] = request(
+            &client,
+            JsonRpcMethod::GetTransactionStatus,
+            json!({ "transaction_hash": pre_confirmed_tx }),
+        )
+        .await?;
+        fixture["pre_confirmed_receipt"] = request(
+            &client,
+            JsonRpcMethod::GetTransactionReceipt,
+            json!({ "transaction_hash": pre_confirmed_tx }),
+        )
+        .await?;
+    }
+
+    std::fs::write(out, serde_json::to_string_pretty(&fixture)? + "\n")?;
+    println!("Recorded spec {version} responses to {out}");
+    Ok(())
+}
@@ -0,0 +1,527 @@
+use crate::{
+    spec::EmittedEvent,
+    types::{ContractEvent, TransactionEvent},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use starknet::core::{
+    codec::Decode,
+    types::{ByteArray, Felt, U256},
+    utils::get_selector_from_name,
+};
+use std::{collections::HashMap, path::Path, slice, sync::Arc};
+
+type Reader<'a> = slice::Iter<'a, Felt>;
+
+/// A Cairo 1 contract ABI, decoding events, calldata and return values into JSON without
+/// generated types.
+#[derive(Debug, Clone)]
+pub struct Abi {
+    functions: HashMap<String, Function>,
+    structs: HashMap<String, Vec<Member>>,
+    enums: HashMap<String, Vec<Member>>,
+    event_types: HashMap<String, Event>,
+    // Top-level event variants by the selector in their first key
+    events: HashMap<Felt, EventVariant>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Entry {
+    Function(Function),
+    Constructor(Function),
+    L1Handler(Function),
+    Interface {
+        items: Vec<Entry>,
+    },
+    Struct {
+        name: String,
+        members: Vec<Member>,
+    },
+    Enum {
+        name: String,
+        variants: Vec<Member>,
+    },
+    Event(Event),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Function {
+    name: String,
+    inputs: Vec<Member>,
+    #[serde(default)]
+    outputs: Vec<Member>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Member {
+    // Outputs are unnamed
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Event {
+    name: String,
+    kind: EventKind,
+    #[serde(default)]
+    members: Vec<EventVariant>,
+    #[serde(default)]
+    variants: Vec<EventVariant>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EventKind {
+    Struct,
+    Enum,
+}
+
+/// Member of a struct event or variant of an enum event.
+#[derive(Debug, Clone, Deserialize)]
+struct EventVariant {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+    kind: MemberKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MemberKind {
+    Key,
+    Data,
+    Nested,
+    Flat,
+}
+
+impl Abi {
+    /// Parses either a bare ABI array or a contract class whose `abi` holds one, as JSON or as a
+    /// JSON string (Sierra classes embed it that way).
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let mut value: Value = serde_json::from_str(json)?;
+        if let Some(abi) = value.get_mut("abi") {
+            value = match abi.take() {
+                Value::String(abi) => serde_json::from_str(&abi)?,
+                abi => abi,
+            };
+        }
+        let entries: Vec<Entry> =
+            serde_json::from_value(value).map_err(|e| anyhow::anyhow!("Invalid ABI: {e}"))?;
+
+        let mut abi = Self {
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            event_types: HashMap::new(),
+            events: HashMap::new(),
+        };
+        abi.add_entries(entries);
+        abi.index_events()?;
+        Ok(abi)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read ABI {}: {e}", path.display()))?;
+        Self::from_json(&json)
+    }
+
+    fn add_entries(&mut self, entries: Vec<Entry>) {
+        for entry in entries {
+            match entry {
+                Entry::Function(function)
+                | Entry::Constructor(function)
+                | Entry::L1Handler(function) => {
+                    self.functions.insert(function.name.clone(), function);
+                }
+                Entry::Interface { items } => self.add_entries(items),
+                Entry::Struct { name, members } => {
+                    self.structs.insert(name, members);
+                }
+                Entry::Enum { name, variants } => {
+                    self.enums.insert(name, variants);
+                }
+                Entry::Event(event) => {
+                    self.event_types.insert(event.name.clone(), event);
+                }
+                Entry::Other => {}
+            }
+        }
+    }
+
+    /// Registers the variants of the contract's event enum, the one no other event nests, by
+    /// their selector. Flat variants contribute their own variants instead.
+    fn index_events(&mut self) -> anyhow::Result<()> {
+        let nested: Vec<&str> = self
+            .event_types
+            .values()
+            .flat_map(|e| &e.variants)
+            .map(|v| v.ty.as_str())
+            .collect();
+        let mut pending: Vec<EventVariant> = self
+            .event_types
+            .values()
+            .filter(|e| e.kind == EventKind::Enum && !nested.contains(&e.name.as_str()))
+            .flat_map(|e| e.variants.clone())
+            .collect();
+
+        while let Some(variant) = pending.pop() {
+            if variant.kind == MemberKind::Flat {
+                pending.extend(self.event_type(&variant.ty)?.variants.clone());
+                continue;
+            }
+            let selector = get_selector_from_name(&variant.name)
+                .map_err(|e| anyhow::anyhow!("Invalid event name {}: {e}", variant.name))?;
+            self.events.insert(selector, variant);
+        }
+        Ok(())
+    }
+
+    /// Selectors of every event the contract emits, i.e. the first keys to filter on.
+    pub fn event_selectors(&self) -> Vec<Felt> {
+        let mut selectors: Vec<Felt> = self.events.keys().copied().collect();
+        selectors.sort();
+        selectors
+    }
+
+    pub fn function_selector(&self, function: &str) -> anyhow::Result<Felt> {
+        self.function(function)?;
+        get_selector_from_name(function)
+            .map_err(|e| anyhow::anyhow!("Invalid function name {function}: {e}"))
+    }
+
+    /// Decodes an event into its variant name and an object of its members, nested events
+    /// becoming nested objects.
+    pub fn decode_event(&self, keys: &[Felt], data: &[Felt]) -> anyhow::Result<ContractEvent> {
+        let (selector, keys) = keys
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("Event without keys"))?;
+        let variant = self
+            .events
+            .get(selector)
+            .ok_or_else(|| anyhow::anyhow!("Unknown event selector 0x{selector:x}"))?;
+        let (mut keys, mut data) = (keys.iter(), data.iter());
+        let fields = self.decode_event_type(&variant.ty, &mut keys, &mut data)?;
+        if keys.next().is_some() || data.next().is_some() {
+            anyhow::bail!("Event {} has more keys or data than its ABI", variant.name);
+        }
+        Ok(ContractEvent {
+            name: variant.name.clone(),
+            fields,
+        })
+    }
+
+    /// A decoder for [`crate::events::EventMonitor::watch_contract`], passing every event as a
+    /// [`TransactionEvent::Contract`].
+    pub fn event_decoder(
+        self: &Arc<Self>,
+    ) -> impl Fn(&EmittedEvent) -> anyhow::Result<TransactionEvent> + Send + Sync + 'static {
+        let abi = self.clone();
+        move |event| {
+            abi.decode_event(&event.keys, &event.data)
+                .map(TransactionEvent::Contract)
+        }
+    }
+
+    /// Decodes the calldata of `function` into an object of its inputs.
+    pub fn decode_calldata(&self, function: &str, calldata: &[Felt]) -> anyhow::Result<Value> {
+        let function = self.function(function)?;
+        let mut reader = calldata.iter();
+        let mut inputs = Map::new();
+        for input in &function.inputs {
+            inputs.insert(input.name.clone(), self.decode(&input.ty, &mut reader)?);
+        }
+        if reader.next().is_some() {
+            anyhow::bail!("Calldata of {} is longer than its inputs", function.name);
+        }
+        Ok(Value::Object(inputs))
+    }
+
+    /// Decodes what `function` returned: its single output, or an array of them.
+    pub fn decode_output(&self, function: &str, output: &[Felt]) -> anyhow::Result<Value> {
+        let function = self.function(function)?;
+        let mut reader = output.iter();
+        let mut values = function
+            .outputs
+            .iter()
+            .map(|output| self.decode(&output.ty, &mut reader))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if reader.next().is_some() {
+            anyhow::bail!("Output of {} is longer than its ABI", function.name);
+        }
+        Ok(match values.len() {
+            0 => Value::Null,
+            1 => values.remove(0),
+            _ => Value::Array(values),
+        })
+    }
+
+    /// Decodes one value of the Cairo type `ty`. Integers that may not fit a JSON number (128
+    /// bits and up) become decimal strings, felts and addresses hex strings.
+    pub fn decode_value(&self, ty: &str, felts: &[Felt]) -> anyhow::Result<Value> {
+        let mut reader = felts.iter();
+        let value = self.decode(ty, &mut reader)?;
+        if reader.next().is_some() {
+            anyhow::bail!("Input is longer than {ty}");
+        }
+        Ok(value)
+    }
+
+    fn function(&self, name: &str) -> anyhow::Result<&Function> {
+        self.functions
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown function {name}"))
+    }
+
+    fn event_type(&self, name: &str) -> anyhow::Result<&Event> {
+        self.event_types
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown event type {name}"))
+    }
+
+    fn decode_event_type(
+        &self,
+        ty: &str,
+        keys: &mut Reader,
+        data: &mut Reader,
+    ) -> anyhow::Result<Value> {
+        let event = self.event_type(ty)?;
+        match event.kind {
+            EventKind::Struct => {
+                let mut fields = Map::new();
+                for member in &event.members {
+                    let value = match member.kind {
+                        MemberKind::Key => self.decode(&member.ty, keys)?,
+                        MemberKind::Data => self.decode(&member.ty, data)?,
+                        MemberKind::Nested | MemberKind::Flat => {
+                            self.decode_event_type(&member.ty, keys, data)?
+                        }
+                    };
+                    fields.insert(member.name.clone(), value);
+                }
+                Ok(Value::Object(fields))
+            }
+            // A nested enum event adds the selector of its own variant as the next key
+            EventKind::Enum => {
+                let selector = keys
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Missing variant key of {ty}"))?;
+                let variant = event
+                    .variants
+                    .iter()
+                    .find(|v| get_selector_from_name(&v.name).ok() == Some(*selector))
+                    .ok_or_else(|| anyhow::anyhow!("Unknown variant 0x{selector:x} of {ty}"))?;
+                let value = self.decode_event_type(&variant.ty, keys, data)?;
+                Ok(Value::Object(Map::from_iter([(
+                    variant.name.clone(),
+                    value,
+                )])))
+            }
+        }
+    }
+
+    fn decode(&self, ty: &str, reader: &mut Reader) -> anyhow::Result<Value> {
+        let value = match ty {
+            "()" => Value::Null,
+            "core::felt252"
+            | "core::bytes_31::bytes31"
+            | "core::starknet::contract_address::ContractAddress"
+            | "core::starknet::class_hash::ClassHash"
+            | "core::starknet::eth_address::EthAddress"
+            | "core::starknet::storage_access::StorageAddress" => {
+                Value::String(format!("0x{:x}", Felt::decode_iter(reader)?))
+            }
+            "core::bool" => bool::decode_iter(reader)?.into(),
+            "core::integer::u8" => u8::decode_iter(reader)?.into(),
+            "core::integer::u16" => u16::decode_iter(reader)?.into(),
+            "core::integer::u32" => u32::decode_iter(reader)?.into(),
+            "core::integer::u64" => u64::decode_iter(reader)?.into(),
+            "core::integer::u128" => u128::decode_iter(reader)?.to_string().into(),
+            "core::integer::u256" => U256::decode_iter(reader)?.to_string().into(),
+            "core::integer::i8" => signed::<i8>(reader, ty)?.into(),
+            "core::integer::i16" => signed::<i16>(reader, ty)?.into(),
+            "core::integer::i32" => signed::<i32>(reader, ty)?.into(),
+            "core::integer::i64" => signed::<i64>(reader, ty)?.into(),
+            "core::integer::i128" => i128::decode_iter(reader)?.to_string().into(),
+            "core::byte_array::ByteArray" => String::try_from(ByteArray::decode_iter(reader)?)
+                .map_err(|e| anyhow::anyhow!("Invalid byte array: {e}"))?
+                .into(),
+            _ if ty.starts_with('(') => {
+                let elements = split_types(&ty[1..ty.len() - 1]);
+                Value::Array(
+                    elements
+                        .into_iter()
+                        .map(|element| self.decode(element, reader))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            _ => {
+                if let Some(members) = self.structs.get(ty) {
+                    let mut fields = Map::new();
+                    for member in members {
+                        fields.insert(member.name.clone(), self.decode(&member.ty, reader)?);
+                    }
+                    return Ok(Value::Object(fields));
+                }
+                if let Some(variants) = self.enums.get(ty) {
+                    let index = u32::decode_iter(reader)? as usize;
+                    let variant = variants
+                        .get(index)
+                        .ok_or_else(|| anyhow::anyhow!("Unknown variant {index} of {ty}"))?;
+                    let value = self.decode(&variant.ty, reader)?;
+                    return Ok(Value::Object(Map::from_iter([(
+                        variant.name.clone(),
+                        value,
+                    )])));
+                }
+                match generic(ty)
+                    .as_ref()
+                    .map(|(base, args)| (*base, args.as_slice()))
+                {
+                    Some(("core::array::Array" | "core::array::Span", [element])) => {
+                        let len = u32::decode_iter(reader)?;
+                        Value::Array(
+                            (0..len)
+                                .map(|_| self.decode(element, reader))
+                                .collect::<anyhow::Result<_>>()?,
+                        )
+                    }
+                    Some(("core::zeroable::NonZero", [inner])) => self.decode(inner, reader)?,
+                    _ => anyhow::bail!("Unknown type {ty}"),
+                }
+            }
+        };
+        Ok(value)
+    }
+}
+
+/// Decodes a signed integer narrower than 128 bits, rejecting values out of its range.
+fn signed<T: TryFrom<i128>>(reader: &mut Reader, ty: &str) -> anyhow::Result<T> {
+    let value = i128::decode_iter(reader)?;
+    T::try_from(value).map_err(|_| anyhow::anyhow!("{value} is out of range for {ty}"))
+}
+
+/// Splits `base::<A, B>` into its base and arguments.
+fn generic(ty: &str) -> Option<(&str, Vec<&str>)> {
+    let (base, args) = ty.split_once("::<")?;
+    Some((base, split_types(args.strip_suffix('>')?)))
+}
+
+/// Splits a comma separated list of types at the top nesting level.
+fn split_types(types: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in types.char_indices() {
+        match c {
+            '<' | '(' => depth += 1,
+            '>' | ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(types[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = types[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+#[cfg(test)]
+fn bridge_abi() -> Abi {
+    Abi::from_json(include_str!("../abi/bitvm_bridge.json")).unwrap()
+}
+
+#[test]
+fn test_decodes_fixture_bridge_events() {
+    let abi = bridge_abi();
+    assert_eq!(
+        abi.event_selectors(),
+        vec![
+            crate::types::BURN_EVENT_SELECTOR,
+            crate::types::MINT_EVENT_SELECTOR
+        ]
+    );
+
+    let fixture = crate::test_utils::rpc_fixture();
+    let events: Vec<EmittedEvent> =
+        serde_json::from_value(fixture["events"]["events"].clone()).unwrap();
+    let mint = abi.decode_event(&events[0].keys, &events[0].data).unwrap();
+    assert_eq!(mint.name, "Mint");
+    assert_eq!(
+        mint.fields,
+        serde_json::json!({
+            "to": "0x72b128ce0273e453e21b2d96a94bc72f5c297fcddae1a537f17769b4aaea80",
+            "value": 500000,
+        })
+    );
+    let burn = abi.decode_event(&events[1].keys, &events[1].data).unwrap();
+    assert_eq!(burn.name, "Burn");
+    assert_eq!(
+        burn.fields["btc_addr"],
+        "bcrt1phcnl4zcl2fu047pv4wx6y058v8u0n02at6lthvm7pcf2wrvjm5tqatn90k"
+    );
+    assert_eq!(burn.fields["operator_id"], 1);
+
+    // Data the ABI does not account for is rejected rather than silently dropped
+    let mut data = events[0].data.clone();
+    data.push(Felt::ONE);
+    assert!(abi.decode_event(&events[0].keys, &data).is_err());
+}
+
+#[test]
+fn test_decodes_calldata_and_outputs() {
+    let abi = bridge_abi();
+    assert_eq!(
+        abi.function_selector("burn").unwrap(),
+        crate::types::BURN_FUNCTION_SELECTOR
+    );
+    assert!(abi.function_selector("transfer").is_err());
+
+    use starknet::core::codec::Encode;
+    let mut calldata = vec![];
+    ByteArray::from("bc1qexample")
+        .encode(&mut calldata)
+        .unwrap();
+    5u32.encode(&mut calldata).unwrap();
+    500000u64.encode(&mut calldata).unwrap();
+    1u32.encode(&mut calldata).unwrap();
+    assert_eq!(
+        abi.decode_calldata("burn", &calldata).unwrap(),
+        serde_json::json!({
+            "btc_addr": "bc1qexample",
+            "fee_rate": 5,
+            "value": 500000,
+            "operator_id": 1,
+        })
+    );
+
+    let max = Felt::from(u128::MAX);
+    assert_eq!(
+        abi.decode_output("balance_of", &[max, max]).unwrap(),
+        U256::from_words(u128::MAX, u128::MAX).to_string()
+    );
+    assert_eq!(abi.decode_value("core::bool", &[Felt::ONE]).unwrap(), true);
+    // u64 outputs are range checked
+    assert!(
+        abi.decode_output("get_min_confirmations", &[Felt::from(u128::MAX)])
+            .is_err()
+    );
+
+    assert_eq!(
+        abi.decode_value(
+            "(core::integer::i8, core::array::Span::<core::felt252>)",
+            &[-Felt::ONE, Felt::TWO, Felt::ONE, Felt::TWO],
+        )
+        .unwrap(),
+        serde_json::json!([-1, ["0x1", "0x2"]])
+    );
+}
@@ -0,0 +1,135 @@
+//! Typed bindings generated by `build.rs` from the contract ABIs in `abi/`: a struct or enum per
+//! ABI type, a selector and a call builder (external) or async call (view) per function, and the
+//! contract's events under `events`. `abi/README.md` says where the ABIs come from.
+
+#[allow(clippy::too_many_arguments)]
+pub mod bitvm_bridge {
+    include!(concat!(env!("OUT_DIR"), "/bitvm_bridge.rs"));
+}
+
+#[allow(clippy::too_many_arguments)]
+pub mod btc_light_client {
+    include!(concat!(env!("OUT_DIR"), "/btc_light_client.rs"));
+}
+
+use starknet::core::{
+    codec::{Decode, Encode, Error, FeltWriter},
+    types::Felt,
+};
+
+/// Cairo's signed integers narrower than `i128`, which starknet-rs has no codec for. They are
+/// encoded like `i128` and rejected when out of range.
+macro_rules! signed_integers {
+    ($($name:ident($ty:ty)),*) => {$(
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub $ty);
+
+        impl Encode for $name {
+            fn encode<W: FeltWriter>(&self, writer: &mut W) -> Result<(), Error> {
+                i128::from(self.0).encode(writer)
+            }
+        }
+
+        impl<'a> Decode<'a> for $name {
+            fn decode_iter<T>(iter: &mut T) -> Result<Self, Error>
+            where
+                T: Iterator<Item = &'a Felt>,
+            {
+                let value = i128::decode_iter(iter)?;
+                <$ty>::try_from(value).map(Self).map_err(|_| {
+                    Error::custom(format!("{value} is out of range for {}", stringify!($ty)))
+                })
+            }
+        }
+    )*};
+}
+
+signed_integers!(I8(i8), I16(i16), I32(i32), I64(i64));
+
+/// Cairo tuples, which starknet-rs has no codec for, encoded element after element.
+macro_rules! tuples {
+    ($($name:ident($($element:ident),+)),*) => {$(
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub struct $name<$($element),+>($(pub $element),+);
+
+        impl<$($element: Encode),+> Encode for $name<$($element),+> {
+            #[allow(non_snake_case)]
+            fn encode<W: FeltWriter>(&self, writer: &mut W) -> Result<(), Error> {
+                let Self($($element),+) = self;
+                $($element.encode(writer)?;)+
+                Ok(())
+            }
+        }
+
+        impl<'a, $($element: Decode<'a>),+> Decode<'a> for $name<$($element),+> {
+            fn decode_iter<I>(iter: &mut I) -> Result<Self, Error>
+            where
+                I: Iterator<Item = &'a Felt>,
+            {
+                Ok(Self($(<$element>::decode_iter(iter)?),+))
+            }
+        }
+    )*};
+}
+
+tuples!(
+    Tuple1(A),
+    Tuple2(A, B),
+    Tuple3(A, B, C),
+    Tuple4(A, B, C, D),
+    Tuple5(A, B, C, D, E),
+    Tuple6(A, B, C, D, E, F),
+    Tuple7(A, B, C, D, E, F, G),
+    Tuple8(A, B, C, D, E, F, G, H)
+);
+
+#[test]
+fn test_generated_bindings_match_the_abi() {
+    use crate::{abi::Abi, spec::EmittedEvent};
+    use bitvm_bridge::events::Event;
+    use starknet::core::types::{ByteArray, Felt};
+
+    let fixture = crate::test_utils::rpc_fixture();
+    let events: Vec<EmittedEvent> =
+        serde_json::from_value(fixture["events"]["events"].clone()).unwrap();
+    let Event::Mint(mint) = Event::decode(&events[0].keys, &events[0].data).unwrap() else {
+        panic!("Expected a Mint event");
+    };
+    assert_eq!(mint.value, 500000);
+    let Event::Burn(burn) = Event::decode(&events[1].keys, &events[1].data).unwrap() else {
+        panic!("Expected a Burn event");
+    };
+    assert_eq!(burn.operator_id, 1);
+    assert!(Event::decode(&[Felt::ONE], &[]).is_err());
+
+    let abi = Abi::from_json(include_str!("../abi/bitvm_bridge.json")).unwrap();
+    let call = bitvm_bridge::burn(
+        Felt::from(0x37fu64),
+        &ByteArray::from("bc1qexample"),
+        5,
+        500000,
+        1,
+    )
+    .unwrap();
+    assert_eq!(call.selector, abi.function_selector("burn").unwrap());
+    assert_eq!(
+        abi.decode_calldata("burn", &call.calldata).unwrap(),
+        serde_json::json!({
+            "btc_addr": "bc1qexample",
+            "fee_rate": 5,
+            "value": 500000,
+            "operator_id": 1,
+        })
+    );
+}
+
+#[test]
+fn test_signed_integers_and_tuples_round_trip() {
+    let value = Tuple2(I8(-5), vec![I64(i64::MIN), I64(7)]);
+    let mut calldata: Vec<Felt> = vec![];
+    value.encode(&mut calldata).unwrap();
+    assert_eq!(calldata[0], Felt::from(-5i128));
+    assert_eq!(Tuple2::<I8, Vec<I64>>::decode(&calldata).unwrap(), value);
+    // An i16 does not fit an i8
+    assert!(I8::decode(&[Felt::from(-129i128)]).is_err());
+}
@@ -1,26 +1,24 @@
 use crate::{
+    bindings::{
+        bitvm_bridge::{self, Peg},
+        btc_light_client,
+    },
     chain::StarknetChainId,
+    provider::FailoverTransport,
     query_client::QueryClient,
-    types::{BURN_FUNCTION_SELECTOR, MINT_FUNCTION_SELECTOR, Peg, PegContext, TransactionStatus},
-    utils::felt_to_u64,
+    spec::SpecVersion,
+    types::{BtcAmount, PegContext, TransactionStatus},
 };
 use anyhow::Ok;
 use starknet::{
     accounts::{Account, ConnectedAccount, ExecutionEncoding, SingleOwnerAccount},
-    core::{
-        codec::Encode,
-        types::{BlockId, BlockTag, ByteArray, Call, Felt, FunctionCall},
-        utils::get_selector_from_name,
-    },
-    providers::{
-        Provider, Url,
-        jsonrpc::{HttpTransport, JsonRpcClient},
-    },
+    core::types::{BlockId, BlockTag, ByteArray, Felt},
+    providers::jsonrpc::JsonRpcClient,
     signers::{LocalWallet, SigningKey},
 };
 
 pub struct BitvmBridgeClient {
-    account: SingleOwnerAccount<JsonRpcClient<HttpTransport>, LocalWallet>,
+    account: SingleOwnerAccount<JsonRpcClient<FailoverTransport>, LocalWallet>,
     bitvm_bridge_contract: Felt,
     btc_light_client_contract: Felt,
     query_client: QueryClient,
@@ -35,8 +33,27 @@ impl BitvmBridgeClient {
         account_contract_address: &str,
         chain_id: &StarknetChainId,
     ) -> Self {
-        let provider =
-            JsonRpcClient::new(HttpTransport::new(Url::parse(url).expect("Invalid URL")));
+        Self::from_transport(
+            FailoverTransport::new(&[url]),
+            bitvm_bridge_contract,
+            btc_light_client_contract,
+            private_key,
+            account_contract_address,
+            chain_id,
+        )
+    }
+
+    /// Like [`BitvmBridgeClient::new`], but sharing a (possibly multi-endpoint) transport with
+    /// other clients.
+    pub fn from_transport(
+        transport: FailoverTransport,
+        bitvm_bridge_contract: &str,
+        btc_light_client_contract: &str,
+        private_key: &str,
+        account_contract_address: &str,
+        chain_id: &StarknetChainId,
+    ) -> Self {
+        let provider = JsonRpcClient::new(transport.clone());
 
         let signer = LocalWallet::from(SigningKey::from_secret_scalar(
             Felt::from_hex(private_key).expect("Invalid Starknet private key"),
@@ -58,7 +75,7 @@ impl BitvmBridgeClient {
             chain_id.to_felt(),
             ExecutionEncoding::New,
         );
-        let query_client = QueryClient::new(url);
+        let query_client = QueryClient::from_transport(transport);
         Self {
             account,
             bitvm_bridge_contract,
@@ -73,22 +90,14 @@ impl BitvmBridgeClient {
             .iter()
             .map(|ctx| Peg::try_from(ctx.clone()))
             .collect();
-        let pegs = pegs?;
-
-        // Encode the calldata
-        let mut calldata = vec![];
-        pegs.encode(&mut calldata)?;
+        let call = bitvm_bridge::mint(self.bitvm_bridge_contract, &pegs?)?;
 
         let nonce = self.get_nonce().await?;
 
         // Execute the mint transaction
         let result = self
             .account
-            .execute_v3(vec![Call {
-                to: self.bitvm_bridge_contract,
-                selector: MINT_FUNCTION_SELECTOR,
-                calldata,
-            }])
+            .execute_v3(vec![call])
             .nonce(nonce)
             .send()
             .await?;
@@ -103,25 +112,20 @@ impl BitvmBridgeClient {
         amount: u64,
         operator_id: u32,
     ) -> anyhow::Result<String> {
-        // Encode the calldata
-        let mut calldata = vec![];
-
-        // Encode each parameter according to the contract ABI
-        ByteArray::from(btc_address).encode(&mut calldata)?;
-        fee_rate.encode(&mut calldata)?;
-        amount.encode(&mut calldata)?;
-        operator_id.encode(&mut calldata)?;
+        let call = bitvm_bridge::burn(
+            self.bitvm_bridge_contract,
+            &ByteArray::from(btc_address),
+            fee_rate,
+            amount,
+            operator_id,
+        )?;
 
         let nonce = self.get_nonce().await?;
 
         // Execute the burn transaction
         let result = self
             .account
-            .execute_v3(vec![Call {
-                to: self.bitvm_bridge_contract,
-                selector: BURN_FUNCTION_SELECTOR,
-                calldata,
-            }])
+            .execute_v3(vec![call])
             .nonce(nonce)
             .send()
             .await?;
@@ -129,57 +133,57 @@ impl BitvmBridgeClient {
         Ok(result.transaction_hash.to_hex_string())
     }
 
+    pub async fn spec_version(&self) -> anyhow::Result<SpecVersion> {
+        self.query_client.spec_version().await
+    }
+
     pub async fn get_transaction_status(&self, tx_hash: &str) -> anyhow::Result<TransactionStatus> {
         self.query_client.get_transaction_status(tx_hash).await
     }
 
-    pub async fn query_latest_block_height(&self) -> anyhow::Result<u64> {
-        let block_height = self
-            .query_light_client_state(&FunctionCall {
-                contract_address: self.btc_light_client_contract,
-                entry_point_selector: get_selector_from_name("get_latest_block_height")
-                    .map_err(|_| anyhow::anyhow!("Invalid latest_block_height selector"))?,
-                calldata: vec![],
-            })
-            .await?;
+    /// Statuses of many transactions in as few requests as possible; see
+    /// [`QueryClient::get_transaction_statuses`].
+    pub async fn get_transaction_statuses(
+        &self,
+        tx_hashes: &[&str],
+    ) -> anyhow::Result<Vec<anyhow::Result<TransactionStatus>>> {
+        self.query_client.get_transaction_statuses(tx_hashes).await
+    }
 
-        let block_height = block_height
-            .first()
-            .ok_or(anyhow::anyhow!("No block height found"))?;
-        felt_to_u64(block_height)
+    pub async fn query_latest_block_height(&self) -> anyhow::Result<u64> {
+        btc_light_client::get_latest_block_height(
+            self.account.provider(),
+            self.btc_light_client_contract,
+            BlockId::Tag(BlockTag::Latest),
+        )
+        .await
     }
 
     pub async fn query_min_confirmations(&self) -> anyhow::Result<u64> {
-        let min_confirmations = self
-            .query_light_client_state(&FunctionCall {
-                contract_address: self.bitvm_bridge_contract,
-                entry_point_selector: get_selector_from_name("get_min_confirmations")
-                    .map_err(|_| anyhow::anyhow!("Invalid min_confirmations selector"))?,
-                calldata: vec![],
-            })
-            .await?;
-
-        let min_confirmations = min_confirmations
-            .first()
-            .ok_or(anyhow::anyhow!("No min confirmations found"))?;
-        felt_to_u64(min_confirmations)
+        bitvm_bridge::get_min_confirmations(
+            self.account.provider(),
+            self.bitvm_bridge_contract,
+            BlockId::Tag(BlockTag::Latest),
+        )
+        .await
     }
 
-    async fn query_light_client_state(&self, fc: &FunctionCall) -> anyhow::Result<Vec<Felt>> {
-        let state = self
-            .account
-            .provider()
-            .call(fc, BlockId::Tag(BlockTag::Latest))
-            .await?;
-        Ok(state)
+    /// The bridged BTC balance of `account`, decoded from the contract's full `u256`.
+    pub async fn query_balance(&self, account: &str) -> anyhow::Result<BtcAmount> {
+        let account = Felt::from_hex(account)?;
+        bitvm_bridge::balance_of(
+            self.account.provider(),
+            self.bitvm_bridge_contract,
+            account,
+            BlockId::Tag(BlockTag::Latest),
+        )
+        .await?
+        .try_into()
     }
 
     async fn get_nonce(&self) -> anyhow::Result<Felt> {
-        let nonce = self
-            .account
-            .provider()
-            .get_nonce(BlockId::Tag(BlockTag::Pending), self.account.address())
-            .await?;
-        Ok(nonce)
+        self.query_client
+            .get_pending_nonce(self.account.address())
+            .await
     }
 }
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use starknet::core::{chain_id as starknet_chain_id, types::Felt};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum StarknetChainId {
     Sepolia,
     Mainnet,
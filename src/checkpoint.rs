@@ -0,0 +1,222 @@
+use crate::utils::replace_file;
+use async_trait::async_trait;
+use starknet::core::types::Felt;
+use std::{io::ErrorKind, path::PathBuf};
+
+/// The last block whose events were fully handled, with its hash when known so a reorg is still
+/// detected across a restart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub height: u64,
+    pub block_hash: Option<Felt>,
+}
+
+impl Checkpoint {
+    pub fn new(height: u64, block_hash: Option<Felt>) -> Self {
+        Self { height, block_hash }
+    }
+}
+
+/// Durable record of a monitor's progress.
+#[async_trait]
+pub trait CheckpointStore: Send + Sync {
+    /// The saved checkpoint, `None` if nothing was saved yet.
+    async fn load(&self) -> anyhow::Result<Option<Checkpoint>>;
+
+    async fn save(&self, checkpoint: Checkpoint) -> anyhow::Result<()>;
+}
+
+/// Keeps the height and block hash in a small text file, replaced atomically on every save so a
+/// crash never leaves a torn checkpoint behind.
+pub struct FileCheckpointStore {
+    path: PathBuf,
+}
+
+impl FileCheckpointStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for FileCheckpointStore {
+    async fn load(&self) -> anyhow::Result<Option<Checkpoint>> {
+        let content = match tokio::fs::read_to_string(&self.path).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let invalid = || anyhow::anyhow!("Invalid checkpoint in {}", self.path.display());
+        // The height, followed by the block hash when it is known
+        let mut fields = content.split_whitespace();
+        let height = fields
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        let block_hash = fields
+            .next()
+            .map(Felt::from_hex)
+            .transpose()
+            .map_err(|_| invalid())?;
+        Ok(Some(Checkpoint::new(height, block_hash)))
+    }
+
+    async fn save(&self, checkpoint: Checkpoint) -> anyhow::Result<()> {
+        let path = self.path.clone();
+        let content = match checkpoint.block_hash {
+            Some(hash) => format!("{} {hash:#x}", checkpoint.height),
+            None => checkpoint.height.to_string(),
+        };
+        tokio::task::spawn_blocking(move || replace_file(&path, content.as_bytes())).await??;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteCheckpointStore;
+#[cfg(feature = "indexer")]
+pub(crate) use sqlite::{create_checkpoints_table, load_checkpoint, save_checkpoint};
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{Checkpoint, CheckpointStore};
+    use crate::utils::with_connection;
+    use async_trait::async_trait;
+    use rusqlite::{Connection, OptionalExtension, params};
+    use starknet::core::types::Felt;
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    /// Stores checkpoints in a `checkpoints` table keyed by name, so several monitors can share
+    /// one database.
+    pub struct SqliteCheckpointStore {
+        conn: Arc<Mutex<Connection>>,
+        name: String,
+    }
+
+    impl SqliteCheckpointStore {
+        pub fn open(path: impl AsRef<Path>, name: &str) -> anyhow::Result<Self> {
+            Self::from_connection(Connection::open(path)?, name)
+        }
+
+        pub fn open_in_memory(name: &str) -> anyhow::Result<Self> {
+            Self::from_connection(Connection::open_in_memory()?, name)
+        }
+
+        pub fn from_connection(conn: Connection, name: &str) -> anyhow::Result<Self> {
+            create_checkpoints_table(&conn)?;
+            Ok(Self {
+                conn: Arc::new(Mutex::new(conn)),
+                name: name.to_string(),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl CheckpointStore for SqliteCheckpointStore {
+        async fn load(&self) -> anyhow::Result<Option<Checkpoint>> {
+            let name = self.name.clone();
+            with_connection(&self.conn, move |conn| load_checkpoint(conn, &name)).await
+        }
+
+        async fn save(&self, checkpoint: Checkpoint) -> anyhow::Result<()> {
+            let name = self.name.clone();
+            with_connection(&self.conn, move |conn| {
+                save_checkpoint(conn, &name, checkpoint)
+            })
+            .await
+        }
+    }
+
+    pub(crate) fn create_checkpoints_table(conn: &Connection) -> anyhow::Result<()> {
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS checkpoints (
+                name TEXT PRIMARY KEY,
+                height INTEGER NOT NULL,
+                block_hash TEXT
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn load_checkpoint(
+        conn: &Connection,
+        name: &str,
+    ) -> anyhow::Result<Option<Checkpoint>> {
+        let row: Option<(i64, Option<String>)> = conn
+            .query_row(
+                "SELECT height, block_hash FROM checkpoints WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        row.map(|(height, block_hash)| {
+            Ok(Checkpoint::new(
+                height as u64,
+                block_hash.as_deref().map(Felt::from_hex).transpose()?,
+            ))
+        })
+        .transpose()
+    }
+
+    pub(crate) fn save_checkpoint(
+        conn: &Connection,
+        name: &str,
+        checkpoint: Checkpoint,
+    ) -> anyhow::Result<()> {
+        conn.execute(
+            "INSERT INTO checkpoints (name, height, block_hash) VALUES (?1, ?2, ?3)
+             ON CONFLICT(name) DO UPDATE
+             SET height = excluded.height, block_hash = excluded.block_hash",
+            params![
+                name,
+                checkpoint.height as i64,
+                checkpoint.block_hash.map(|hash| format!("{hash:#x}"))
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_file_checkpoint_roundtrip() {
+    let path = std::env::temp_dir().join(format!("checkpoint-{}", rand::random::<u64>()));
+    let store = FileCheckpointStore::new(&path);
+    assert_eq!(store.load().await.unwrap(), None);
+
+    let checkpoint = Checkpoint::new(890970, Some(Felt::from(0xabcu64)));
+    store.save(checkpoint).await.unwrap();
+    assert_eq!(
+        FileCheckpointStore::new(&path).load().await.unwrap(),
+        Some(checkpoint)
+    );
+
+    // Without a block hash the file holds the height alone
+    store.save(Checkpoint::new(890870, None)).await.unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "890870");
+    assert_eq!(
+        store.load().await.unwrap(),
+        Some(Checkpoint::new(890870, None))
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_checkpoints_are_keyed_by_name() {
+    let path = std::env::temp_dir().join(format!("checkpoint-{}.db", rand::random::<u64>()));
+    let bridge = SqliteCheckpointStore::open(&path, "bridge").unwrap();
+    let light_client = SqliteCheckpointStore::open(&path, "light_client").unwrap();
+
+    bridge.save(Checkpoint::new(100, None)).await.unwrap();
+    let checkpoint = Checkpoint::new(200, Some(Felt::from(0xabcu64)));
+    bridge.save(checkpoint).await.unwrap();
+    assert_eq!(bridge.load().await.unwrap(), Some(checkpoint));
+    assert_eq!(light_client.load().await.unwrap(), None);
+    drop((bridge, light_client));
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,244 @@
+use crate::{events::BridgeEvent, utils::replace_file};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use tokio::sync::Mutex;
+
+/// An event whose handler kept failing, set aside so the monitor can move on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub event: BridgeEvent,
+    /// The last handler error
+    pub error: String,
+}
+
+impl DeadLetter {
+    pub fn id(&self) -> String {
+        self.event.meta.id()
+    }
+}
+
+/// Durable storage for dead letters until they are replayed.
+#[async_trait]
+pub trait DeadLetterSink: Send + Sync {
+    /// Stores the letter, replacing an earlier one for the same event.
+    async fn push(&self, letter: DeadLetter) -> anyhow::Result<()>;
+
+    /// Letters not replayed yet, oldest first.
+    async fn pending(&self) -> anyhow::Result<Vec<DeadLetter>>;
+
+    async fn remove(&self, id: &str) -> anyhow::Result<()>;
+}
+
+/// Keeps dead letters as JSON lines in a file.
+pub struct FileDeadLetterSink {
+    path: PathBuf,
+    // Serializes the read-modify-write of `push` and `remove`
+    lock: Mutex<()>,
+}
+
+impl FileDeadLetterSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Rewrites the file with the letters `update` leaves, on a blocking thread.
+    async fn update(
+        &self,
+        update: impl FnOnce(&mut Vec<DeadLetter>) + Send + 'static,
+    ) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut letters = read_letters(&path)?;
+            update(&mut letters);
+            let mut content = String::new();
+            for letter in &letters {
+                content += &serde_json::to_string(letter)?;
+                content.push('\n');
+            }
+            replace_file(&path, content.as_bytes())?;
+            Ok(())
+        })
+        .await?
+    }
+}
+
+fn read_letters(path: &Path) -> anyhow::Result<Vec<DeadLetter>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("Invalid dead letter in {}: {e}", path.display()))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl DeadLetterSink for FileDeadLetterSink {
+    async fn push(&self, letter: DeadLetter) -> anyhow::Result<()> {
+        self.update(move |letters| {
+            letters.retain(|l| l.id() != letter.id());
+            letters.push(letter);
+        })
+        .await
+    }
+
+    async fn pending(&self) -> anyhow::Result<Vec<DeadLetter>> {
+        let _guard = self.lock.lock().await;
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || read_letters(&path)).await?
+    }
+
+    async fn remove(&self, id: &str) -> anyhow::Result<()> {
+        let id = id.to_string();
+        self.update(move |letters| letters.retain(|l| l.id() != id))
+            .await
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteDeadLetterSink;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{DeadLetter, DeadLetterSink};
+    use crate::utils::with_connection;
+    use async_trait::async_trait;
+    use rusqlite::{Connection, params};
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    pub struct SqliteDeadLetterSink {
+        conn: Arc<Mutex<Connection>>,
+    }
+
+    impl SqliteDeadLetterSink {
+        pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+            Self::from_connection(Connection::open(path)?)
+        }
+
+        pub fn open_in_memory() -> anyhow::Result<Self> {
+            Self::from_connection(Connection::open_in_memory()?)
+        }
+
+        pub fn from_connection(conn: Connection) -> anyhow::Result<Self> {
+            conn.execute(
+                "CREATE TABLE IF NOT EXISTS dead_letters (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    id TEXT NOT NULL UNIQUE,
+                    letter TEXT NOT NULL
+                )",
+                [],
+            )?;
+            Ok(Self {
+                conn: Arc::new(Mutex::new(conn)),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl DeadLetterSink for SqliteDeadLetterSink {
+        async fn push(&self, letter: DeadLetter) -> anyhow::Result<()> {
+            let record = serde_json::to_string(&letter)?;
+            with_connection(&self.conn, move |conn| {
+                conn.execute(
+                    "INSERT INTO dead_letters (id, letter) VALUES (?1, ?2)
+                     ON CONFLICT(id) DO UPDATE SET letter = excluded.letter",
+                    params![letter.id(), record],
+                )?;
+                Ok(())
+            })
+            .await
+        }
+
+        async fn pending(&self) -> anyhow::Result<Vec<DeadLetter>> {
+            with_connection(&self.conn, |conn| {
+                let mut stmt = conn.prepare("SELECT letter FROM dead_letters ORDER BY seq")?;
+                let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+                rows.map(|letter| Ok(serde_json::from_str(&letter?)?))
+                    .collect()
+            })
+            .await
+        }
+
+        async fn remove(&self, id: &str) -> anyhow::Result<()> {
+            let id = id.to_string();
+            with_connection(&self.conn, move |conn| {
+                conn.execute("DELETE FROM dead_letters WHERE id = ?1", params![id])?;
+                Ok(())
+            })
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_letter(event_index: u64) -> DeadLetter {
+    use crate::{
+        test_utils::test_meta,
+        types::{MintEventData, TransactionEvent},
+    };
+
+    DeadLetter {
+        event: BridgeEvent {
+            meta: test_meta(0x123, 890870, event_index),
+            payload: TransactionEvent::Mint(MintEventData {
+                to: "0x1".to_string(),
+                value: 500000.into(),
+            }),
+        },
+        error: "Database unavailable".to_string(),
+    }
+}
+
+#[tokio::test]
+async fn test_file_dead_letters_roundtrip() {
+    let path = std::env::temp_dir().join(format!("dead-letters-{}", rand::random::<u64>()));
+    let sink = FileDeadLetterSink::new(&path);
+    assert!(sink.pending().await.unwrap().is_empty());
+
+    sink.push(test_letter(0)).await.unwrap();
+    sink.push(test_letter(1)).await.unwrap();
+    // Failing again replaces the letter instead of duplicating it
+    sink.push(test_letter(0)).await.unwrap();
+    let pending = FileDeadLetterSink::new(&path).pending().await.unwrap();
+    assert_eq!(pending.len(), 2);
+    assert_eq!(pending[0].id(), "0x123:1");
+
+    sink.remove("0x123:1").await.unwrap();
+    let pending = sink.pending().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].event.meta, test_letter(0).event.meta);
+    fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_dead_letters_roundtrip() {
+    let sink = SqliteDeadLetterSink::open_in_memory().unwrap();
+    sink.push(test_letter(0)).await.unwrap();
+    sink.push(test_letter(1)).await.unwrap();
+    sink.push(test_letter(0)).await.unwrap();
+    assert_eq!(sink.pending().await.unwrap().len(), 2);
+
+    sink.remove("0x123:0").await.unwrap();
+    let pending = sink.pending().await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].id(), "0x123:1");
+}
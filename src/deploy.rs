@@ -0,0 +1,270 @@
+use crate::{
+    bridge_client::BitvmBridgeClient, chain::StarknetChainId, provider::FailoverTransport,
+    query_client::QueryClient,
+};
+use serde::{Deserialize, Serialize};
+use starknet::{
+    accounts::{Account, ConnectedAccount, ExecutionEncoding, SingleOwnerAccount},
+    contract::ContractFactory,
+    core::types::{
+        BlockId, BlockTag, Felt, StarknetError,
+        contract::{CompiledClass, SierraClass},
+    },
+    providers::{Provider, ProviderError, jsonrpc::JsonRpcClient},
+    signers::{LocalWallet, SigningKey},
+};
+use std::{path::Path, sync::Arc, time::Duration};
+
+/// Paths to the compiled artifacts of a Cairo contract, as produced by `scarb build`
+/// (`<name>.contract_class.json` and `<name>.compiled_contract_class.json`).
+#[derive(Debug, Clone)]
+pub struct ContractArtifact<'a> {
+    pub sierra_path: &'a Path,
+    pub casm_path: &'a Path,
+}
+
+/// A contract instance deployed through the Universal Deployer Contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DeployedContract {
+    pub address: Felt,
+    pub class_hash: Felt,
+}
+
+/// Everything needed to talk to a freshly deployed bridge environment.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Deployment {
+    pub chain: StarknetChainId,
+    pub bitvm_bridge: DeployedContract,
+    pub btc_light_client: DeployedContract,
+}
+
+impl Deployment {
+    pub fn from_json_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn to_json_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Builds a [`BitvmBridgeClient`] pointing at the deployed contracts.
+    pub fn bridge_client(
+        &self,
+        url: &str,
+        private_key: &str,
+        account_contract_address: &str,
+    ) -> BitvmBridgeClient {
+        BitvmBridgeClient::new(
+            url,
+            &self.bitvm_bridge.address.to_hex_string(),
+            &self.btc_light_client.address.to_hex_string(),
+            private_key,
+            account_contract_address,
+            &self.chain,
+        )
+    }
+}
+
+/// Declares and deploys contracts from a funded account. Intended for devnets and test
+/// environments, not for production rollouts.
+pub struct Deployer {
+    account: Arc<SingleOwnerAccount<JsonRpcClient<FailoverTransport>, LocalWallet>>,
+    chain_id: StarknetChainId,
+    query_client: QueryClient,
+}
+
+impl Deployer {
+    const POLL_INTERVAL: Duration = Duration::from_secs(2);
+    const POLL_ATTEMPTS: u32 = 90;
+
+    pub fn new(
+        url: &str,
+        private_key: &str,
+        account_contract_address: &str,
+        chain_id: &StarknetChainId,
+    ) -> Self {
+        let transport = FailoverTransport::new(&[url]);
+        let provider = JsonRpcClient::new(transport.clone());
+
+        let signer = LocalWallet::from(SigningKey::from_secret_scalar(
+            Felt::from_hex(private_key).expect("Invalid Starknet private key"),
+        ));
+
+        let account_contract_address = Felt::from_hex(account_contract_address)
+            .expect("Invalid user account contract address");
+
+        let account = SingleOwnerAccount::new(
+            provider,
+            signer,
+            account_contract_address,
+            chain_id.to_felt(),
+            ExecutionEncoding::New,
+        );
+        Self {
+            account: Arc::new(account),
+            chain_id: *chain_id,
+            query_client: QueryClient::from_transport(transport),
+        }
+    }
+
+    /// Declares the Sierra class unless it is already known to the network, and returns its
+    /// class hash.
+    pub async fn declare(&self, artifact: &ContractArtifact<'_>) -> anyhow::Result<Felt> {
+        let sierra: SierraClass =
+            serde_json::from_reader(std::fs::File::open(artifact.sierra_path)?).map_err(|e| {
+                anyhow::anyhow!("Invalid Sierra artifact {:?}: {e}", artifact.sierra_path)
+            })?;
+        let casm: CompiledClass = serde_json::from_reader(std::fs::File::open(artifact.casm_path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid CASM artifact {:?}: {e}", artifact.casm_path))?;
+
+        let flattened = sierra.flatten()?;
+        let class_hash = flattened.class_hash();
+        let compiled_class_hash = casm.class_hash()?;
+
+        if self.is_declared(class_hash).await? {
+            return Ok(class_hash);
+        }
+
+        let result = self
+            .account
+            .declare_v3(Arc::new(flattened), compiled_class_hash)
+            .send()
+            .await?;
+        self.wait_for_acceptance(result.transaction_hash).await?;
+
+        Ok(class_hash)
+    }
+
+    /// Deploys an instance of a declared class through the Universal Deployer Contract and waits
+    /// for the deployment to be accepted.
+    pub async fn deploy(
+        &self,
+        class_hash: Felt,
+        constructor_calldata: Vec<Felt>,
+        salt: Felt,
+    ) -> anyhow::Result<DeployedContract> {
+        let factory = ContractFactory::new(class_hash, self.account.clone());
+        let deployment = factory.deploy_v3(constructor_calldata, salt, true);
+        let address = deployment.deployed_address();
+
+        let result = deployment.send().await?;
+        self.wait_for_acceptance(result.transaction_hash).await?;
+
+        Ok(DeployedContract {
+            address,
+            class_hash,
+        })
+    }
+
+    pub async fn declare_and_deploy(
+        &self,
+        artifact: &ContractArtifact<'_>,
+        constructor_calldata: Vec<Felt>,
+        salt: Felt,
+    ) -> anyhow::Result<DeployedContract> {
+        let class_hash = self.declare(artifact).await?;
+        self.deploy(class_hash, constructor_calldata, salt).await
+    }
+
+    /// Stands up the light client and then the bridge. The bridge constructor calldata is built
+    /// from the light client address, since the bridge needs to know where to verify headers.
+    pub async fn deploy_bridge(
+        &self,
+        btc_light_client: &ContractArtifact<'_>,
+        btc_light_client_calldata: Vec<Felt>,
+        bitvm_bridge: &ContractArtifact<'_>,
+        bitvm_bridge_calldata: impl FnOnce(Felt) -> Vec<Felt>,
+        salt: Felt,
+    ) -> anyhow::Result<Deployment> {
+        let btc_light_client = self
+            .declare_and_deploy(btc_light_client, btc_light_client_calldata, salt)
+            .await?;
+        let bitvm_bridge = self
+            .declare_and_deploy(
+                bitvm_bridge,
+                bitvm_bridge_calldata(btc_light_client.address),
+                salt,
+            )
+            .await?;
+
+        Ok(Deployment {
+            chain: self.chain_id,
+            bitvm_bridge,
+            btc_light_client,
+        })
+    }
+
+    async fn is_declared(&self, class_hash: Felt) -> anyhow::Result<bool> {
+        // Declarations are awaited until accepted, so `latest` sees them; unlike the pending
+        // block's tag it means the same on every spec version
+        match self
+            .account
+            .provider()
+            .get_class(BlockId::Tag(BlockTag::Latest), class_hash)
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(ProviderError::StarknetError(StarknetError::ClassHashNotFound)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn wait_for_acceptance(&self, tx_hash: Felt) -> anyhow::Result<()> {
+        let tx_hash = tx_hash.to_hex_string();
+        for _ in 0..Self::POLL_ATTEMPTS {
+            match self.query_client.get_transaction_status(&tx_hash).await {
+                Ok(status) if status.is_rejected() => anyhow::bail!(
+                    "Transaction {tx_hash} rejected: {}",
+                    status.failure_reason.unwrap_or_default()
+                ),
+                Ok(status) if status.is_reverted() => anyhow::bail!(
+                    "Transaction {tx_hash} reverted: {}",
+                    status.failure_reason.unwrap_or_default()
+                ),
+                Ok(status) if status.is_accepted() => return Ok(()),
+                Ok(_) => {}
+                // The node may not know about the transaction yet right after submission
+                Err(e)
+                    if matches!(
+                        e.downcast_ref::<ProviderError>(),
+                        Some(ProviderError::StarknetError(
+                            StarknetError::TransactionHashNotFound
+                        ))
+                    ) => {}
+                Err(e) => return Err(e),
+            }
+            tokio::time::sleep(Self::POLL_INTERVAL).await;
+        }
+        anyhow::bail!("Timed out waiting for transaction {tx_hash}")
+    }
+}
+
+#[test]
+fn test_deployment_json_roundtrip() {
+    let deployment = Deployment {
+        chain: StarknetChainId::Sepolia,
+        bitvm_bridge: DeployedContract {
+            address: Felt::from_hex_unchecked(
+                "0x37f3357511947cc872aad08b97c49986b90479053630bffb8eeb968b757d255",
+            ),
+            class_hash: Felt::from(1u8),
+        },
+        btc_light_client: DeployedContract {
+            address: Felt::from_hex_unchecked(
+                "0x2a8812cad5c0b3ba20b97a0e519a6a5363849881958d2529a1e5313e55233cd",
+            ),
+            class_hash: Felt::from(2u8),
+        },
+    };
+
+    let json = serde_json::to_string(&deployment).unwrap();
+    assert!(json.contains("\"chain\":\"sepolia\""));
+    assert!(json.contains("0x37f3357511947cc872aad08b97c49986b90479053630bffb8eeb968b757d255"));
+    assert_eq!(
+        serde_json::from_str::<Deployment>(&json).unwrap(),
+        deployment
+    );
+}
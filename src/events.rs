@@ -1,55 +1,474 @@
 use crate::{
-    types::{BURN_EVENT_SELECTOR, MINT_EVENT_SELECTOR, TransactionEvent},
-    utils::{block_timestamp, parse_event},
+    checkpoint::{Checkpoint, CheckpointStore},
+    dead_letter::{DeadLetter, DeadLetterSink},
+    provider::FailoverTransport,
+    query_client::QueryClient,
+    retry::RetryPolicy,
+    spec::{BlockHeader, EmittedEvent, SpecVersion},
+    types::{BURN_EVENT_SELECTOR, BtcAmount, ContractEvent, MINT_EVENT_SELECTOR, TransactionEvent},
+    utils::parse_event,
+    ws::{Notification, WsClient},
 };
 use async_trait::async_trait;
-use starknet::{
-    core::types::{BlockId, EventFilter, Felt},
-    providers::{
-        Provider, Url,
-        jsonrpc::{HttpTransport, JsonRpcClient},
-    },
+use futures::{Stream, StreamExt, stream};
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::{BlockId, EventFilter, Felt};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fmt,
+    num::NonZeroUsize,
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
 };
 
-/// Event handler trait for processing bridge events
+/// A bridge `Mint` with where it was emitted.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MintEvent {
+    pub meta: EventMeta,
+    pub to: String,
+    pub value: BtcAmount,
+}
+
+/// A bridge `Burn` with where it was emitted.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BurnEvent {
+    pub meta: EventMeta,
+    pub from: String,
+    pub btc_addr: String,
+    pub value: BtcAmount,
+    pub fee_rate: u32,
+    pub operator_id: u32,
+}
+
+/// Receives what the monitor sees. Every method defaults to doing nothing, so handlers only
+/// implement what they need.
+///
+/// Each batch of blocks is delivered as `on_batch_start`, its events in chain order, then
+/// `on_batch_commit`. A batch that fails in between is started again later without being
+/// committed, so handlers that buffer a batch should drop it on the next `on_batch_start`.
+///
+/// Async closures taking a [`MonitorEvent`] and tokio mpsc senders of [`MonitorEvent`] are
+/// handlers too, and so is a `Vec<Box<dyn EventHandler>>`, which passes every call to each of
+/// its handlers in order.
 #[async_trait]
 pub trait EventHandler: Send + Sync {
-    #[allow(clippy::too_many_arguments)]
-    async fn handle_mint(
+    async fn handle_mint(&self, event: &MintEvent) -> anyhow::Result<()> {
+        let _ = event;
+        Ok(())
+    }
+
+    async fn handle_burn(&self, event: &BurnEvent) -> anyhow::Result<()> {
+        let _ = event;
+        Ok(())
+    }
+
+    /// Called for [`TransactionEvent::Contract`] events, of contracts added with
+    /// [`EventMonitor::watch_contract`] or registered with [`EventMonitor::with_event`].
+    async fn handle_contract_event(
         &self,
-        block_number: u64,
-        block_timestamp: u64,
-        tx_hash: &str,
-        to: &str,
-        value: u64,
-    ) -> anyhow::Result<()>;
+        meta: &EventMeta,
+        event: &ContractEvent,
+    ) -> anyhow::Result<()> {
+        let _ = (meta, event);
+        Ok(())
+    }
 
-    #[allow(clippy::too_many_arguments)]
-    async fn handle_burn(
+    /// Called in [`FinalityMode::PreConfirmed`] for events of the block that is still being built.
+    /// They may never make it into the chain; those that do are delivered again through
+    /// `handle_mint`/`handle_burn` once confirmed.
+    async fn handle_unconfirmed(
         &self,
-        block_number: u64,
-        block_timestamp: u64,
+        block_number: Option<u64>,
         tx_hash: &str,
-        from: &str,
-        btc_addr: &str,
-        value: u64,
-        fee_rate: u64,
-        operator_id: u64,
-    ) -> anyhow::Result<()>;
+        event: &TransactionEvent,
+    ) -> anyhow::Result<()> {
+        let _ = (block_number, tx_hash, event);
+        Ok(())
+    }
+
+    /// Called before the events of blocks `from_height..=to_height` are delivered.
+    async fn on_batch_start(&self, from_height: u64, to_height: u64) -> anyhow::Result<()> {
+        let _ = (from_height, to_height);
+        Ok(())
+    }
+
+    /// Called once every event up to `to_height` was handled, right before the checkpoint is
+    /// saved. Failing here fails the batch, which is then delivered again.
+    async fn on_batch_commit(&self, to_height: u64) -> anyhow::Result<()> {
+        let _ = to_height;
+        Ok(())
+    }
+
+    /// Called when the chain was reorganized: everything handled from `from_height` on is no
+    /// longer canonical and must be rolled back. Those blocks are delivered again afterwards.
+    async fn on_reorg(&self, from_height: u64) -> anyhow::Result<()> {
+        let _ = from_height;
+        Ok(())
+    }
+}
+
+/// Every [`EventHandler`] call as a value, for handlers that are closures or channels.
+#[derive(Debug, Clone)]
+pub enum MonitorEvent {
+    Mint(MintEvent),
+    Burn(BurnEvent),
+    Contract {
+        meta: EventMeta,
+        event: ContractEvent,
+    },
+    Unconfirmed {
+        block_number: Option<u64>,
+        tx_hash: String,
+        event: TransactionEvent,
+    },
+    BatchStart {
+        from_height: u64,
+        to_height: u64,
+    },
+    BatchCommit {
+        to_height: u64,
+    },
+    Reorg {
+        from_height: u64,
+    },
+}
+
+// Implements `EventHandler` by turning every call into a `MonitorEvent` passed to `$send`
+macro_rules! impl_event_handler {
+    ([$($generics:tt)*] $ty:ty, |$this:ident, $event:ident| $send:expr) => {
+        #[async_trait]
+        impl<$($generics)*> EventHandler for $ty {
+            async fn handle_mint(&self, event: &MintEvent) -> anyhow::Result<()> {
+                let ($this, $event) = (self, MonitorEvent::Mint(event.clone()));
+                $send
+            }
+
+            async fn handle_burn(&self, event: &BurnEvent) -> anyhow::Result<()> {
+                let ($this, $event) = (self, MonitorEvent::Burn(event.clone()));
+                $send
+            }
+
+            async fn handle_contract_event(
+                &self,
+                meta: &EventMeta,
+                event: &ContractEvent,
+            ) -> anyhow::Result<()> {
+                let $event = MonitorEvent::Contract {
+                    meta: meta.clone(),
+                    event: event.clone(),
+                };
+                let $this = self;
+                $send
+            }
+
+            async fn handle_unconfirmed(
+                &self,
+                block_number: Option<u64>,
+                tx_hash: &str,
+                event: &TransactionEvent,
+            ) -> anyhow::Result<()> {
+                let $event = MonitorEvent::Unconfirmed {
+                    block_number,
+                    tx_hash: tx_hash.to_string(),
+                    event: event.clone(),
+                };
+                let $this = self;
+                $send
+            }
+
+            async fn on_batch_start(&self, from_height: u64, to_height: u64) -> anyhow::Result<()> {
+                let $event = MonitorEvent::BatchStart {
+                    from_height,
+                    to_height,
+                };
+                let $this = self;
+                $send
+            }
+
+            async fn on_batch_commit(&self, to_height: u64) -> anyhow::Result<()> {
+                let ($this, $event) = (self, MonitorEvent::BatchCommit { to_height });
+                $send
+            }
+
+            async fn on_reorg(&self, from_height: u64) -> anyhow::Result<()> {
+                let ($this, $event) = (self, MonitorEvent::Reorg { from_height });
+                $send
+            }
+        }
+    };
+}
+
+impl_event_handler!(
+    [F: Fn(MonitorEvent) -> Fut + Send + Sync, Fut: Future<Output = anyhow::Result<()>> + Send]
+    F,
+    |handler, event| handler(event).await
+);
+
+impl_event_handler!(
+    [] tokio::sync::mpsc::Sender<MonitorEvent>,
+    |sender, event| sender
+        .send(event)
+        .await
+        .map_err(|_| anyhow::anyhow!("Event receiver was dropped"))
+);
+
+impl_event_handler!(
+    [] tokio::sync::mpsc::UnboundedSender<MonitorEvent>,
+    |sender, event| sender
+        .send(event)
+        .map_err(|_| anyhow::anyhow!("Event receiver was dropped"))
+);
+
+// Several handlers as one, each call passed to every handler in order. A handler failing stops
+// the call, and a retry starts over with the first handler, so they must all be idempotent
+impl_event_handler!(
+    [] Vec<Box<dyn EventHandler>>,
+    |handlers, event| {
+        for handler in handlers {
+            deliver(handler.as_ref(), &event).await?;
+        }
+        Ok(())
+    }
+);
+
+/// Makes the [`EventHandler`] call `event` stands for.
+async fn deliver(handler: &dyn EventHandler, event: &MonitorEvent) -> anyhow::Result<()> {
+    match event {
+        MonitorEvent::Mint(mint) => handler.handle_mint(mint).await,
+        MonitorEvent::Burn(burn) => handler.handle_burn(burn).await,
+        MonitorEvent::Contract { meta, event } => handler.handle_contract_event(meta, event).await,
+        MonitorEvent::Unconfirmed {
+            block_number,
+            tx_hash,
+            event,
+        } => {
+            handler
+                .handle_unconfirmed(*block_number, tx_hash, event)
+                .await
+        }
+        MonitorEvent::BatchStart {
+            from_height,
+            to_height,
+        } => handler.on_batch_start(*from_height, *to_height).await,
+        MonitorEvent::BatchCommit { to_height } => handler.on_batch_commit(*to_height).await,
+        MonitorEvent::Reorg { from_height } => handler.on_reorg(*from_height).await,
+    }
+}
+
+/// Which blocks the monitor treats as final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FinalityMode {
+    /// Blocks at least `confirmed_blocks` below the latest block
+    #[default]
+    Depth,
+    /// Only blocks the node reports as ACCEPTED_ON_L1, ignoring `confirmed_blocks`
+    AcceptedOnL1,
+    /// Like `Depth`, and additionally every event of the pending / pre-confirmed block is passed
+    /// to [`EventHandler::handle_unconfirmed`] as soon as it is seen
+    PreConfirmed,
+}
+
+/// What happens to an event that fails to decode, or whose handler still fails after the
+/// configured retries.
+#[derive(Clone, Default)]
+pub enum FailureAction {
+    /// Log the error through the `log` facade and move on; the event is lost
+    Skip,
+    /// Abort the batch without advancing the checkpoint, so it is redelivered on the next poll
+    #[default]
+    Halt,
+    /// Store the event in the sink, to be replayed through
+    /// [`EventMonitor::replay_dead_letters`]
+    DeadLetter(Arc<dyn DeadLetterSink>),
+}
+
+impl fmt::Debug for FailureAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Skip => write!(f, "Skip"),
+            Self::Halt => write!(f, "Halt"),
+            Self::DeadLetter(_) => write!(f, "DeadLetter"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MonitorConfig {
+    /// Events requested per `starknet_getEvents` page
+    pub chunk_size: u64,
+    /// Blocks scanned, and checkpointed, at a time
+    pub blocks_per_batch: u64,
+    /// Depth below the latest block considered final in `Depth` and `PreConfirmed` mode
+    pub confirmed_blocks: u64,
+    pub finality: FinalityMode,
+    /// Retries of a failing handler call before `on_handler_failure` applies
+    pub handler_retry: RetryPolicy,
+    pub on_handler_failure: FailureAction,
+    /// Batches [`EventMonitor::backfill`] fetches concurrently
+    pub backfill_workers: usize,
+    /// Block headers, with their transaction hashes, kept in memory so blocks are not fetched
+    /// again for every batch
+    pub block_cache_size: usize,
+    /// Blocks reprocessed below the oldest tracked block when the chain was reorganized past it,
+    /// e.g. while the monitor was down and only the checkpointed block hash is known
+    pub reorg_depth: u64,
+    /// Delay between polls of [`EventMonitor::run`] once caught up, growing with every poll
+    /// that finds no new block. Polling never gives up, so `max_attempts` is ignored
+    pub poll_backoff: RetryPolicy,
+    /// Delay after a failed poll, growing with consecutive failures; `max_attempts` is ignored
+    pub error_backoff: RetryPolicy,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 100,
+            blocks_per_batch: 100,
+            confirmed_blocks: 6,
+            finality: FinalityMode::Depth,
+            handler_retry: RetryPolicy::default(),
+            on_handler_failure: FailureAction::Halt,
+            backfill_workers: 4,
+            block_cache_size: 1024,
+            reorg_depth: 64,
+            poll_backoff: RetryPolicy {
+                initial_backoff: Duration::from_secs(2),
+                max_backoff: Duration::from_secs(10),
+                jitter: false,
+                ..RetryPolicy::default()
+            },
+            error_backoff: RetryPolicy {
+                initial_backoff: Duration::from_secs(5),
+                max_backoff: Duration::from_secs(60),
+                jitter: false,
+                ..RetryPolicy::default()
+            },
+        }
+    }
+}
+
+/// Turns an event of a watched contract into a [`TransactionEvent`]. Events it fails on are
+/// skipped.
+pub type EventDecoder =
+    Arc<dyn Fn(&EmittedEvent) -> anyhow::Result<TransactionEvent> + Send + Sync>;
+
+/// The events of a contract the monitor decodes, by selector (the event's first key). The
+/// default registry has the bridge's `Mint` and `Burn`.
+#[derive(Clone)]
+pub struct EventRegistry {
+    decoders: BTreeMap<Felt, EventDecoder>,
+}
+
+impl EventRegistry {
+    /// A registry without any events, not even the built-in ones.
+    pub fn empty() -> Self {
+        Self {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    /// Decodes events with `selector` by `decoder`. Custom events are usually decoded into
+    /// [`TransactionEvent::Contract`], which reaches [`EventHandler::handle_contract_event`].
+    pub fn register(
+        mut self,
+        selector: Felt,
+        decoder: impl Fn(&EmittedEvent) -> anyhow::Result<TransactionEvent> + Send + Sync + 'static,
+    ) -> anyhow::Result<Self> {
+        if self.decoders.contains_key(&selector) {
+            anyhow::bail!("Event 0x{selector:x} is already registered");
+        }
+        self.decoders.insert(selector, Arc::new(decoder));
+        Ok(self)
+    }
+
+    pub fn selectors(&self) -> Vec<Felt> {
+        self.decoders.keys().copied().collect()
+    }
+
+    pub fn decode(&self, event: &EmittedEvent) -> anyhow::Result<TransactionEvent> {
+        let selector = event
+            .keys
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Event without keys"))?;
+        let decoder = self
+            .decoders
+            .get(selector)
+            .ok_or_else(|| anyhow::anyhow!("Unregistered event 0x{selector:x}"))?;
+        decoder(event)
+    }
+}
+
+impl Default for EventRegistry {
+    fn default() -> Self {
+        Self::empty()
+            .register(MINT_EVENT_SELECTOR, parse_event)
+            .and_then(|events| events.register(BURN_EVENT_SELECTOR, parse_event))
+            .expect("Mint and Burn have distinct selectors")
+    }
+}
+
+/// Name of the [`ContractEvent`] a dead letter holds in place of an event of a watched contract
+/// that failed to decode. Its fields are the raw `keys` and `data`, which
+/// [`EventMonitor::replay_dead_letters`] decodes again.
+pub const UNDECODED_EVENT: &str = "undecoded";
+
+fn undecoded(event: &EmittedEvent) -> TransactionEvent {
+    TransactionEvent::Contract(ContractEvent {
+        name: UNDECODED_EVENT.to_string(),
+        fields: serde_json::json!({ "keys": event.keys, "data": event.data }),
+    })
+}
+
+/// A contract whose events the monitor scans.
+#[derive(Clone)]
+pub(crate) struct WatchedContract {
+    address: Felt,
+    events: EventRegistry,
+}
+
+impl WatchedContract {
+    /// The bridge at `address`, with its `Mint` and `Burn` events.
+    pub(crate) fn bridge(address: Felt) -> Self {
+        Self {
+            address,
+            events: EventRegistry::default(),
+        }
+    }
 }
 
 /// Monitor for bridge events
 pub struct EventMonitor {
-    contract_address: Felt,
+    // The bridge first, then the contracts added with `watch_contract`
+    contracts: Vec<WatchedContract>,
     handler: Box<dyn EventHandler>,
-    provider: JsonRpcClient<HttpTransport>,
+    query_client: Arc<QueryClient>,
     last_processed_height: u64,
+    config: MonitorConfig,
+    checkpoint_store: Option<Box<dyn CheckpointStore>>,
+    checkpoint_loaded: bool,
+    // Hashes of processed blocks (batch boundaries and blocks with events), used to find where a
+    // reorganized chain forked off
+    block_hashes: BTreeMap<u64, Felt>,
+    block_cache: LruCache<u64, BlockHeader>,
+    l1_accepted_height: u64,
+    // Pending transactions whose events were passed to `handle_unconfirmed`
+    unconfirmed_delivered: HashMap<Felt, PendingTransaction>,
+}
+
+/// How far the monitor got with the events of a pending transaction.
+#[derive(Debug, Default)]
+struct PendingTransaction {
+    // Block the transaction was last seen pending in; once it is processed without the
+    // transaction, the transaction was dropped
+    block_number: u64,
+    // Events already passed to `handle_unconfirmed`
+    delivered: usize,
 }
 
 impl EventMonitor {
-    const CHUNK_SIZE: u64 = 100;
-    const CONFIRMED_BLOCKS: u64 = 6;
-    const BLOCKS_PER_BATCH: u64 = 100;
+    const TRACKED_BLOCK_HASHES: usize = 256;
 
     pub fn new(
         contract_address: &str,
@@ -57,143 +476,2270 @@ impl EventMonitor {
         handler: Box<dyn EventHandler>,
         last_processed_height: u64,
     ) -> Self {
-        let provider = JsonRpcClient::new(HttpTransport::new(
-            Url::parse(rpc_url).expect("Invalid starknet RPC URL"),
-        ));
+        Self::from_transport(
+            contract_address,
+            FailoverTransport::new(&[rpc_url]),
+            handler,
+            last_processed_height,
+        )
+    }
+
+    pub fn from_transport(
+        contract_address: &str,
+        transport: FailoverTransport,
+        handler: Box<dyn EventHandler>,
+        last_processed_height: u64,
+    ) -> Self {
+        let query_client = Arc::new(QueryClient::from_transport(transport));
         let contract_address =
             Felt::from_hex(contract_address).expect("Invalid starknet contract address");
         Self {
-            contract_address,
+            contracts: vec![WatchedContract::bridge(contract_address)],
             handler,
-            provider,
+            query_client,
             last_processed_height,
+            config: MonitorConfig::default(),
+            checkpoint_store: None,
+            checkpoint_loaded: false,
+            block_hashes: BTreeMap::new(),
+            block_cache: LruCache::new(
+                NonZeroUsize::new(MonitorConfig::default().block_cache_size).unwrap(),
+            ),
+            l1_accepted_height: 0,
+            unconfirmed_delivered: HashMap::new(),
         }
     }
 
-    pub async fn process(&mut self) -> anyhow::Result<()> {
-        let latest_block_number = self.latest_block_number().await?;
+    pub fn with_config(mut self, config: MonitorConfig) -> Self {
+        assert!(config.chunk_size > 0, "Chunk size must be positive");
+        assert!(
+            config.blocks_per_batch > 0,
+            "Blocks per batch must be positive"
+        );
+        assert!(
+            config.backfill_workers > 0,
+            "Backfill workers must be positive"
+        );
+        let block_cache_size =
+            NonZeroUsize::new(config.block_cache_size).expect("Block cache size must be positive");
+        self.block_cache.resize(block_cache_size);
+        self.config = config;
+        self
+    }
 
-        // Safety check: ensure we have enough confirmed blocks
-        if latest_block_number < Self::CONFIRMED_BLOCKS {
-            return Ok(());
-        }
+    /// Also decodes the bridge's events with `selector` by `decoder`, next to `Mint` and `Burn`.
+    pub fn with_event(
+        mut self,
+        selector: Felt,
+        decoder: impl Fn(&EmittedEvent) -> anyhow::Result<TransactionEvent> + Send + Sync + 'static,
+    ) -> anyhow::Result<Self> {
+        let bridge = &mut self.contracts[0];
+        bridge.events = bridge.events.clone().register(selector, decoder)?;
+        Ok(self)
+    }
 
-        let end_block = latest_block_number - Self::CONFIRMED_BLOCKS;
+    /// Also scans `address` for events whose first key is one of `selectors`, decoded by
+    /// `decoder`. Its events share the bridge's block scan, ordering and checkpoint.
+    pub fn watch_contract(
+        self,
+        address: &str,
+        selectors: &[Felt],
+        decoder: impl Fn(&EmittedEvent) -> anyhow::Result<TransactionEvent> + Send + Sync + 'static,
+    ) -> anyhow::Result<Self> {
+        let decoder: EventDecoder = Arc::new(decoder);
+        let events = selectors
+            .iter()
+            .try_fold(EventRegistry::empty(), |events, &selector| {
+                let decoder = decoder.clone();
+                events.register(selector, move |event| decoder(event))
+            })?;
+        self.watch_contract_events(address, events)
+    }
 
-        // Early return if nothing to process
-        if self.last_processed_height >= end_block {
-            return Ok(());
+    /// Like [`EventMonitor::watch_contract`], with the contract's events in a registry.
+    pub fn watch_contract_events(
+        mut self,
+        address: &str,
+        events: EventRegistry,
+    ) -> anyhow::Result<Self> {
+        let address = Felt::from_hex(address)
+            .map_err(|_| anyhow::anyhow!("Invalid starknet contract address {address}"))?;
+        if self.contracts.iter().any(|c| c.address == address) {
+            anyhow::bail!("Contract 0x{address:x} is already watched");
         }
+        self.contracts.push(WatchedContract { address, events });
+        Ok(self)
+    }
 
-        let mut current_block = self.last_processed_height;
+    pub fn config(&self) -> &MonitorConfig {
+        &self.config
+    }
 
-        while current_block < end_block {
-            let from_height = current_block + 1;
-            let to_height = (from_height + Self::BLOCKS_PER_BATCH - 1).min(end_block);
+    /// Resume from the height saved in `store`, falling back to the height given at construction
+    /// when it is empty, and save progress there after every handled batch.
+    pub fn with_checkpoint_store(mut self, store: Box<dyn CheckpointStore>) -> Self {
+        self.checkpoint_store = Some(store);
+        self.checkpoint_loaded = false;
+        self
+    }
 
-            // Process events for this batch of blocks
-            let mut continuation_token = None;
+    pub async fn process(&mut self) -> anyhow::Result<()> {
+        self.load_checkpoint().await?;
+        let end_block = self.final_height().await?;
 
-            loop {
-                let response = self
-                    .provider
-                    .get_events(
-                        EventFilter {
-                            from_block: Some(BlockId::Number(from_height)),
-                            to_block: Some(BlockId::Number(to_height)),
-                            address: Some(self.contract_address),
-                            keys: Some(vec![vec![MINT_EVENT_SELECTOR, BURN_EVENT_SELECTOR]]),
-                        },
-                        continuation_token,
-                        Self::CHUNK_SIZE,
-                    )
-                    .await?;
+        while self.last_processed_height < end_block {
+            if let Some(batch) = self.fetch_batch(end_block).await? {
+                self.handle_batch(batch).await?;
+            }
+        }
 
-                // Process events in this response
-                for event in response.events {
-                    if let Err(e) = self.process_single_event(event).await {
-                        eprintln!("Error processing event: {}", e);
-                        // Continue processing other events rather than failing completely
+        if self.config.finality == FinalityMode::PreConfirmed {
+            self.process_unconfirmed().await?;
+        }
+        Ok(())
+    }
+
+    /// Catches up to `to_height`, capped at the final height, with `config.backfill_workers`
+    /// batches fetched concurrently ahead of the one being handled. Events still reach the handler
+    /// in block and event order, and every batch is checkpointed as in [`Self::process`].
+    /// `progress` is called after each batch.
+    pub async fn backfill(
+        &mut self,
+        to_height: u64,
+        mut progress: impl FnMut(&BackfillProgress),
+    ) -> anyhow::Result<()> {
+        self.load_checkpoint().await?;
+        let to_height = to_height.min(self.final_height().await?);
+        let mut report = BackfillProgress {
+            start_height: self.last_processed_height,
+            target_height: to_height,
+            processed_height: self.last_processed_height,
+            events: 0,
+        };
+
+        // A reorg rolls back the processed height, so the windows are planned again from there
+        while self.last_processed_height < to_height {
+            let query_client = self.query_client.clone();
+            let contracts = Arc::new(self.contracts.clone());
+            let chunk_size = self.config.chunk_size;
+            let blocks_per_batch = self.config.blocks_per_batch;
+            let windows = (self.last_processed_height + 1..=to_height)
+                .step_by(blocks_per_batch as usize)
+                .map(move |from| (from, (from + blocks_per_batch - 1).min(to_height)));
+            let mut fetched = stream::iter(windows)
+                .map(|(from_height, to_height)| {
+                    let (query_client, contracts) = (query_client.clone(), contracts.clone());
+                    async move {
+                        let events = fetch_events(
+                            &query_client,
+                            &contracts,
+                            from_height,
+                            to_height,
+                            chunk_size,
+                        )
+                        .await?;
+                        // Prefetch the headers the batch needs; the first block is always
+                        // fetched again as the reorg probe
+                        let block_numbers: BTreeSet<u64> = events
+                            .iter()
+                            .filter_map(|e| e.block_number)
+                            .chain([to_height])
+                            .collect();
+                        let block_ids: Vec<BlockId> =
+                            block_numbers.iter().map(|n| BlockId::Number(*n)).collect();
+                        let headers = query_client.get_block_headers(&block_ids).await?;
+                        anyhow::Ok((from_height, to_height, events, headers))
+                    }
+                })
+                .buffered(self.config.backfill_workers);
+
+            while let Some(window) = fetched.next().await {
+                let (from_height, to_height, events, headers) = window?;
+                for header in headers {
+                    let header = header?;
+                    if let Some(number) = header.block_number {
+                        self.block_cache.put(number, header);
                     }
                 }
+                let Some(batch) = self.build_batch(from_height, to_height, events).await? else {
+                    break;
+                };
+                report.events += batch.events.len();
+                self.handle_batch(batch).await?;
+                report.processed_height = self.last_processed_height;
+                progress(&report);
+            }
+        }
+        Ok(())
+    }
 
-                // Update continuation token for next iteration
-                continuation_token = response.continuation_token;
+    /// Polls until `shutdown` completes: back to back while catching up, then sleeping between
+    /// polls for new blocks (longer the longer the chain is idle) and backing off after errors,
+    /// which are logged rather than returned. Shutdown is only observed between batches, so the
+    /// batch in progress is finished and checkpointed first.
+    pub async fn run(&mut self, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+        tokio::pin!(shutdown);
+        let mut end_block = 0;
+        let mut idle_polls = 0;
+        let mut failures = 0;
 
-                // If no continuation token, we've got all events for this batch
-                if continuation_token.is_none() {
-                    break;
-                }
+        loop {
+            if futures::poll!(shutdown.as_mut()).is_ready() {
+                return Ok(());
             }
 
-            current_block = to_height;
+            let progress = match self.step(&mut end_block).await {
+                Ok(Step::Batch(batch)) => self.handle_batch(batch).await.map(|()| true),
+                Ok(Step::RolledBack) => Ok(true),
+                Ok(Step::Idle) => Ok(false),
+                Err(e) => Err(e),
+            };
+            let delay = match progress {
+                Ok(true) => {
+                    idle_polls = 0;
+                    failures = 0;
+                    continue;
+                }
+                Ok(false) => {
+                    failures = 0;
+                    idle_polls += 1;
+                    self.config.poll_backoff.backoff(idle_polls)
+                }
+                Err(e) => {
+                    log::warn!("Error processing events: {e:#}");
+                    failures += 1;
+                    self.config.error_backoff.backoff(failures)
+                }
+            };
+
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut shutdown => return Ok(()),
+            }
         }
+    }
 
-        self.last_processed_height = current_block;
-        Ok(())
+    /// Like [`EventMonitor::run`], but woken by `starknet_subscribeNewHeads` instead of polling
+    /// and fed by `starknet_subscribeEvents` instead of `starknet_getEvents`. Blocks missed
+    /// while (re)connecting are caught up by polling before subscribing again.
+    pub async fn run_ws(
+        &mut self,
+        ws_url: &str,
+        shutdown: impl Future<Output = ()>,
+    ) -> anyhow::Result<()> {
+        tokio::pin!(shutdown);
+        let mut failures = 0;
+
+        loop {
+            match self
+                .follow_subscription(ws_url, shutdown.as_mut(), &mut failures)
+                .await
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    log::warn!("Error following event subscription: {e:#}");
+                    failures += 1;
+                }
+            }
+
+            let delay = self.config.error_backoff.backoff(failures);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut shutdown => return Ok(()),
+            }
+        }
     }
 
-    async fn process_single_event(
-        &self,
-        event: starknet::core::types::EmittedEvent,
+    /// Catches up by polling, then handles subscription notifications until `shutdown`
+    /// completes (`Ok`) or the connection fails.
+    async fn follow_subscription(
+        &mut self,
+        ws_url: &str,
+        mut shutdown: Pin<&mut impl Future<Output = ()>>,
+        failures: &mut u32,
     ) -> anyhow::Result<()> {
-        let block_number = event.block_number.unwrap_or(0);
-        let tx_hash = format!("0x{:x}", event.transaction_hash);
+        let mut client = WsClient::connect(ws_url).await?;
+
+        let mut end_block = 0;
+        loop {
+            if futures::poll!(shutdown.as_mut()).is_ready() {
+                return Ok(());
+            }
+            match self.step(&mut end_block).await? {
+                Step::Batch(batch) => self.handle_batch(batch).await?,
+                Step::RolledBack => {}
+                Step::Idle => break,
+            }
+        }
 
-        // Get block timestamp (cache this if processing many events from same block)
-        let block_timestamp = if block_number > 0 {
-            let block_with_txs = self
-                .provider
-                .get_block_with_tx_hashes(BlockId::Number(block_number))
+        // Events of every block from here on arrive through the subscription
+        let mut covered_from = self.last_processed_height + 1;
+        client.subscribe_new_heads().await?;
+        for contract in &self.contracts {
+            client
+                .subscribe_events(
+                    contract.address,
+                    vec![contract.events.selectors()],
+                    Some(covered_from),
+                )
                 .await?;
-            block_timestamp(&block_with_txs)
-        } else {
-            0
-        };
+        }
+        *failures = 0;
 
-        if let Ok(parsed_event) = parse_event(&event) {
-            match parsed_event {
-                TransactionEvent::Test(test_event) => {
-                    println!("Test event: {:?}", test_event);
-                }
-                TransactionEvent::Mint(mint_event) => {
-                    self.handler
-                        .handle_mint(
-                            block_number,
-                            block_timestamp,
-                            &tx_hash,
-                            &mint_event.to,
-                            mint_event.value,
-                        )
-                        .await?;
+        let mut buffered: BTreeMap<u64, Vec<EmittedEvent>> = BTreeMap::new();
+        loop {
+            let notification = tokio::select! {
+                notification = client.next_notification() => notification?,
+                _ = &mut shutdown => return Ok(()),
+            };
+            match notification {
+                Notification::Event(event) => {
+                    if let Some(block_number) = event.block_number
+                        && block_number > self.last_processed_height
+                    {
+                        buffered.entry(block_number).or_default().push(event);
+                    }
                 }
-                TransactionEvent::Burn(burn_event) => {
-                    self.handler
-                        .handle_burn(
-                            block_number,
-                            block_timestamp,
-                            &tx_hash,
-                            &burn_event.from,
-                            &burn_event.btc_addr,
-                            burn_event.value,
-                            burn_event.fee_rate as u64,
-                            burn_event.operator_id as u64,
-                        )
+                Notification::Reorg {
+                    starting_block_number,
+                    ..
+                } => {
+                    // The replacement blocks' events follow
+                    buffered.split_off(&starting_block_number);
+                }
+                Notification::NewHead(_) => {
+                    let end_block = self.final_height().await?;
+                    while self.last_processed_height < end_block {
+                        if futures::poll!(shutdown.as_mut()).is_ready() {
+                            return Ok(());
+                        }
+                        let from_height = self.last_processed_height + 1;
+                        // Blocks below the subscription's start, e.g. after a rollback, are
+                        // fetched by polling
+                        let batch = if from_height < covered_from {
+                            self.fetch_batch(end_block.min(covered_from - 1)).await?
+                        } else {
+                            let to_height =
+                                (from_height + self.config.blocks_per_batch - 1).min(end_block);
+                            let rest = buffered.split_off(&(to_height + 1));
+                            let events = std::mem::replace(&mut buffered, rest);
+                            covered_from = to_height + 1;
+                            let mut events: Vec<_> = events.into_values().flatten().collect();
+                            locate_events(&self.query_client, &mut events).await?;
+                            self.build_batch(from_height, to_height, events).await?
+                        };
+                        if let Some(batch) = batch {
+                            self.handle_batch(batch).await?;
+                        }
+                    }
+                    if self.config.finality == FinalityMode::PreConfirmed {
+                        self.process_unconfirmed().await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Every event as a [`BridgeEvent`] stream that tails the chain like [`EventMonitor::run`]
+    /// and never ends on its own. A batch is checkpointed once all of its events have been
+    /// pulled, so a slow consumer holds the monitor back instead of events piling up. Reorgs and
+    /// unconfirmed events still go to the handler given at construction.
+    pub fn into_stream(self) -> impl Stream<Item = anyhow::Result<BridgeEvent>> + Send {
+        let state = EventStream {
+            monitor: self,
+            end_block: 0,
+            queue: VecDeque::new(),
+            uncommitted: None,
+            delay: None,
+            idle_polls: 0,
+            failures: 0,
+        };
+        stream::unfold(state, |mut state| async move {
+            let item = state.next_event().await;
+            Some((item, state))
+        })
+    }
+
+    /// Fetches the next batch below `end_block`, refreshing it once caught up.
+    async fn step(&mut self, end_block: &mut u64) -> anyhow::Result<Step> {
+        self.load_checkpoint().await?;
+        if self.last_processed_height >= *end_block {
+            *end_block = self.final_height().await?;
+            if self.config.finality == FinalityMode::PreConfirmed {
+                self.process_unconfirmed().await?;
+            }
+            if self.last_processed_height >= *end_block {
+                return Ok(Step::Idle);
+            }
+        }
+        Ok(match self.fetch_batch(*end_block).await? {
+            Some(batch) => Step::Batch(batch),
+            None => Step::RolledBack,
+        })
+    }
+
+    /// Fetches the events of the next batch of blocks up to `end_block`, or rolls back to the fork
+    /// point and returns `None` if the chain was reorganized.
+    async fn fetch_batch(&mut self, end_block: u64) -> anyhow::Result<Option<Batch>> {
+        let from_height = self.last_processed_height + 1;
+        let to_height = (from_height + self.config.blocks_per_batch - 1).min(end_block);
+        let events = fetch_events(
+            &self.query_client,
+            &self.contracts,
+            from_height,
+            to_height,
+            self.config.chunk_size,
+        )
+        .await?;
+        self.build_batch(from_height, to_height, events).await
+    }
+
+    /// Decodes the events of blocks `from_height..=to_height` into a batch, or rolls back to the
+    /// fork point and returns `None` if those blocks no longer extend the processed chain.
+    async fn build_batch(
+        &mut self,
+        from_height: u64,
+        to_height: u64,
+        mut events: Vec<EmittedEvent>,
+    ) -> anyhow::Result<Option<Batch>> {
+        // The first block is the reorg probe, so it always comes from the node
+        self.block_cache.pop(&from_height);
+        // Fetch the boundaries and every block with events in one round trip
+        let mut headers = self
+            .block_headers(
+                [from_height, to_height]
+                    .into_iter()
+                    .chain(events.iter().filter_map(|e| e.block_number)),
+            )
+            .await?;
+
+        // A cached header that disagrees with the block hash of an event is stale; fetch it again
+        let stale: Vec<u64> = events
+            .iter()
+            .filter(|e| !in_block(e, &headers))
+            .filter_map(|e| e.block_number)
+            .collect();
+        if !stale.is_empty() {
+            for block_number in &stale {
+                self.block_cache.pop(block_number);
+            }
+            headers.extend(self.block_headers(stale).await?);
+            if let Some(event) = events.iter().find(|e| !in_block(e, &headers)) {
+                anyhow::bail!(
+                    "Block {} changed while the events of transaction 0x{:x} were fetched",
+                    event.block_number.unwrap_or_default(),
+                    event.transaction_hash
+                );
+            }
+        }
+
+        // The batch must extend the chain processed so far, otherwise roll back to the fork
+        if let Some(fork_height) = self.find_fork(&headers[&from_height]).await? {
+            self.rollback(fork_height).await?;
+            return Ok(None);
+        }
+
+        order_events(&mut events, &headers)?;
+
+        let mut bridge_events = vec![];
+        for event in events {
+            self.unconfirmed_delivered.remove(&event.transaction_hash);
+
+            // Events that do not decode are not ours to handle
+            let Some(contract) = self
+                .contracts
+                .iter()
+                .find(|c| c.address == event.from_address)
+            else {
+                continue;
+            };
+            let header = event.block_number.and_then(|n| headers.get(&n));
+            let meta = EventMeta::locate(&event, header)?;
+            match contract.events.decode(&event) {
+                Ok(payload) => bridge_events.push(BridgeEvent { meta, payload }),
+                Err(e) => {
+                    let event = BridgeEvent {
+                        meta,
+                        payload: undecoded(&event),
+                    };
+                    self.on_failure(&event, e.context("Failed to decode the event"))
                         .await?;
                 }
             }
         }
+
+        Ok(Some(Batch {
+            from_height,
+            to_height,
+            events: bridge_events,
+            headers,
+        }))
+    }
+
+    /// Passes the batch's events to the handler and checkpoints it.
+    async fn handle_batch(&mut self, batch: Batch) -> anyhow::Result<()> {
+        self.handler
+            .on_batch_start(batch.from_height, batch.to_height)
+            .await?;
+        for event in &batch.events {
+            if let Err(e) = self.handle_with_retry(event).await {
+                self.on_failure(event, e).await?;
+            }
+        }
+        self.handler.on_batch_commit(batch.to_height).await?;
+        self.commit_batch(&batch).await
+    }
+
+    /// Applies `on_handler_failure` to an event that failed to decode or to be handled.
+    async fn on_failure(&self, event: &BridgeEvent, e: anyhow::Error) -> anyhow::Result<()> {
+        match &self.config.on_handler_failure {
+            FailureAction::Skip => {
+                log::error!("Skipping event {}: {e:#}", event.meta.id());
+            }
+            FailureAction::Halt => {
+                return Err(e.context(format!("Failed to process event {}", event.meta.id())));
+            }
+            FailureAction::DeadLetter(sink) => {
+                let letter = DeadLetter {
+                    event: event.clone(),
+                    error: format!("{e:#}"),
+                };
+                sink.push(letter).await.map_err(|sink_error| {
+                    anyhow::anyhow!(
+                        "Failed to dead-letter event {} ({e}): {sink_error}",
+                        event.meta.id()
+                    )
+                })?;
+            }
+        }
         Ok(())
     }
 
-    pub fn processed_height(&self) -> u64 {
-        self.last_processed_height
+    async fn handle_with_retry(&self, event: &BridgeEvent) -> anyhow::Result<()> {
+        let retry = &self.config.handler_retry;
+        let mut attempt = 1;
+        loop {
+            match self.handle_event(event).await {
+                Err(_) if attempt < retry.max_attempts => {
+                    tokio::time::sleep(retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
     }
 
-    pub async fn latest_block_number(&self) -> anyhow::Result<u64> {
-        self.provider
-            .block_number()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to get latest block number: {}", e))
+    /// Hands every dead letter to the handler again, removing those it now accepts. Returns how
+    /// many were replayed; the others keep their place with the new error.
+    pub async fn replay_dead_letters(&self) -> anyhow::Result<usize> {
+        let FailureAction::DeadLetter(sink) = &self.config.on_handler_failure else {
+            anyhow::bail!("No dead letter sink configured");
+        };
+        let mut replayed = 0;
+        for letter in sink.pending().await? {
+            let result = match self.decode_again(&letter.event) {
+                Ok(event) => self.handle_event(&event).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => {
+                    sink.remove(&letter.id()).await?;
+                    replayed += 1;
+                }
+                Err(e) => {
+                    sink.push(DeadLetter {
+                        error: format!("{e:#}"),
+                        ..letter
+                    })
+                    .await?
+                }
+            }
+        }
+        Ok(replayed)
+    }
+
+    /// Decodes the raw event of a letter whose event failed to decode, with the contract's
+    /// current registry; other events are returned as they are.
+    fn decode_again(&self, event: &BridgeEvent) -> anyhow::Result<BridgeEvent> {
+        let TransactionEvent::Contract(raw) = &event.payload else {
+            return Ok(event.clone());
+        };
+        if raw.name != UNDECODED_EVENT {
+            return Ok(event.clone());
+        }
+        let meta = &event.meta;
+        let contract = self
+            .contracts
+            .iter()
+            .find(|c| c.address == meta.from_address)
+            .ok_or_else(|| anyhow::anyhow!("0x{:x} is not watched", meta.from_address))?;
+        let emitted = EmittedEvent {
+            from_address: meta.from_address,
+            keys: serde_json::from_value(raw.fields["keys"].clone())?,
+            data: serde_json::from_value(raw.fields["data"].clone())?,
+            block_hash: Some(meta.block_hash),
+            block_number: Some(meta.block_number),
+            transaction_hash: meta.tx_hash,
+            transaction_index: meta.tx_index,
+            event_index: Some(meta.event_index),
+        };
+        Ok(BridgeEvent {
+            meta: meta.clone(),
+            payload: contract
+                .events
+                .decode(&emitted)
+                .map_err(|e| e.context("Failed to decode the event"))?,
+        })
+    }
+
+    async fn commit_batch(&mut self, batch: &Batch) -> anyhow::Result<()> {
+        let block_hash = batch
+            .headers
+            .get(&batch.to_height)
+            .and_then(|header| header.block_hash);
+        self.commit(batch.to_height, block_hash).await?;
+        self.record_block_hashes(batch.headers.values().cloned());
+        Ok(())
+    }
+
+    /// Highest block whose events may be handled under the configured finality mode.
+    pub async fn final_height(&mut self) -> anyhow::Result<u64> {
+        match self.config.finality {
+            FinalityMode::Depth | FinalityMode::PreConfirmed => {
+                let latest_block_number = self.latest_block_number().await?;
+                Ok(latest_block_number.saturating_sub(self.config.confirmed_blocks))
+            }
+            FinalityMode::AcceptedOnL1 => {
+                self.l1_accepted_height = self
+                    .query_client
+                    .l1_accepted_block_number(self.l1_accepted_height)
+                    .await?;
+                Ok(self.l1_accepted_height)
+            }
+        }
     }
+
+    /// Passes events of the pending block not seen before to the handler. Nothing is
+    /// checkpointed; an event whose handler fails is retried on the next call under `Halt`, and
+    /// left to the confirmed delivery otherwise.
+    async fn process_unconfirmed(&mut self) -> anyhow::Result<()> {
+        // Transactions still pending after their block was processed were dropped
+        let processed_height = self.last_processed_height;
+        self.unconfirmed_delivered
+            .retain(|_, tx| tx.block_number > processed_height);
+
+        // Pending events carry no block number before v0.9
+        let mut pending_block_number = None;
+        let mut seen: HashMap<Felt, usize> = HashMap::new();
+        for contract in self.contracts.clone() {
+            let mut continuation_token = None;
+            loop {
+                let response = self
+                    .query_client
+                    .get_pending_events(
+                        contract.address,
+                        vec![contract.events.selectors()],
+                        continuation_token,
+                        self.config.chunk_size,
+                    )
+                    .await?;
+
+                for event in response.events {
+                    let block_number = match event.block_number.or(pending_block_number) {
+                        Some(block_number) => block_number,
+                        None => {
+                            let block_number = self.latest_block_number().await? + 1;
+                            pending_block_number = Some(block_number);
+                            block_number
+                        }
+                    };
+                    let tx_hash = event.transaction_hash;
+                    let position = seen.entry(tx_hash).or_default();
+                    *position += 1;
+                    let tx = self.unconfirmed_delivered.entry(tx_hash).or_default();
+                    tx.block_number = tx.block_number.max(block_number);
+                    if *position <= tx.delivered {
+                        continue;
+                    }
+                    let Ok(parsed_event) = contract.events.decode(&event) else {
+                        tx.delivered += 1;
+                        continue;
+                    };
+                    let result = self
+                        .handler
+                        .handle_unconfirmed(
+                            event.block_number,
+                            &format!("0x{tx_hash:x}"),
+                            &parsed_event,
+                        )
+                        .await;
+                    if let Err(e) = result {
+                        if matches!(self.config.on_handler_failure, FailureAction::Halt) {
+                            return Err(e.context(format!(
+                                "Failed to handle unconfirmed event of transaction 0x{tx_hash:x}"
+                            )));
+                        }
+                        log::warn!("Error processing unconfirmed event of 0x{tx_hash:x}: {e:#}");
+                    }
+                    self.unconfirmed_delivered
+                        .get_mut(&tx_hash)
+                        .unwrap()
+                        .delivered += 1;
+                }
+
+                continuation_token = response.continuation_token;
+                if continuation_token.is_none() {
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_checkpoint(&mut self) -> anyhow::Result<()> {
+        if self.checkpoint_loaded {
+            return Ok(());
+        }
+        if let Some(store) = &self.checkpoint_store
+            && let Some(checkpoint) = store.load().await?
+        {
+            self.last_processed_height = checkpoint.height;
+            // The first batch after a restart is checked for a reorg against it
+            if let Some(hash) = checkpoint.block_hash {
+                self.block_hashes.insert(checkpoint.height, hash);
+            }
+        }
+        self.checkpoint_loaded = true;
+        Ok(())
+    }
+
+    /// Marks every block up to `height` as handled, persisting it before moving on so a crash
+    /// never skips a batch.
+    async fn commit(&mut self, height: u64, block_hash: Option<Felt>) -> anyhow::Result<()> {
+        if let Some(store) = &self.checkpoint_store {
+            store
+                .save(Checkpoint::new(height, block_hash))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to save checkpoint {height}: {e}"))?;
+        }
+        self.last_processed_height = height;
+        Ok(())
+    }
+
+    /// Headers of the given blocks, taken from the cache where possible; the others are fetched
+    /// together in batched requests.
+    async fn block_headers(
+        &mut self,
+        block_numbers: impl IntoIterator<Item = u64>,
+    ) -> anyhow::Result<HashMap<u64, BlockHeader>> {
+        let mut headers = HashMap::new();
+        let mut missing = vec![];
+        for number in block_numbers.into_iter().collect::<BTreeSet<_>>() {
+            match self.block_cache.get(&number) {
+                Some(header) => {
+                    headers.insert(number, header.clone());
+                }
+                None => missing.push(number),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(headers);
+        }
+
+        let block_ids: Vec<BlockId> = missing.iter().map(|n| BlockId::Number(*n)).collect();
+        let fetched = self.query_client.get_block_headers(&block_ids).await?;
+        for (number, header) in missing.into_iter().zip(fetched) {
+            let header = header?;
+            self.block_cache.put(number, header.clone());
+            headers.insert(number, header);
+        }
+        Ok(headers)
+    }
+
+    /// Checks the parent of the next block to process against the recorded hash and, if the chain
+    /// was reorganized, returns the newest recorded height that is still canonical. When none is,
+    /// the fork is taken to be `config.reorg_depth` blocks below the oldest one.
+    async fn find_fork(&self, next_block: &BlockHeader) -> anyhow::Result<Option<u64>> {
+        let Some(parent_height) = next_block.block_number.and_then(|n| n.checked_sub(1)) else {
+            return Ok(None);
+        };
+        match self.block_hashes.get(&parent_height) {
+            Some(parent_hash) if next_block.parent_hash != Some(*parent_hash) => {}
+            _ => return Ok(None),
+        }
+
+        let heights: Vec<u64> = self.block_hashes.keys().rev().copied().collect();
+        let block_ids: Vec<BlockId> = heights.iter().map(|n| BlockId::Number(*n)).collect();
+        let headers = self.query_client.get_block_headers(&block_ids).await?;
+        for (height, header) in heights.iter().zip(headers) {
+            if header?.block_hash == self.block_hashes.get(height).copied() {
+                return Ok(Some(*height));
+            }
+        }
+        let oldest = heights.last().copied().unwrap_or_default();
+        let fork_height = oldest.saturating_sub(self.config.reorg_depth);
+        log::warn!(
+            "Chain reorganized below the oldest tracked block {oldest}, reprocessing from block {}",
+            fork_height + 1
+        );
+        Ok(Some(fork_height))
+    }
+
+    /// Undoes everything above `fork_height` so the next batch reprocesses it.
+    async fn rollback(&mut self, fork_height: u64) -> anyhow::Result<()> {
+        self.handler.on_reorg(fork_height + 1).await?;
+        self.block_hashes.split_off(&(fork_height + 1));
+        let replaced: Vec<u64> = self
+            .block_cache
+            .iter()
+            .map(|(number, _)| *number)
+            .filter(|number| *number > fork_height)
+            .collect();
+        for number in replaced {
+            self.block_cache.pop(&number);
+        }
+        let block_hash = self.block_hashes.get(&fork_height).copied();
+        self.commit(fork_height, block_hash).await
+    }
+
+    fn record_block_hashes(&mut self, headers: impl IntoIterator<Item = BlockHeader>) {
+        for header in headers {
+            if let (Some(number), Some(hash)) = (header.block_number, header.block_hash) {
+                self.block_hashes.insert(number, hash);
+            }
+        }
+        while self.block_hashes.len() > Self::TRACKED_BLOCK_HASHES {
+            self.block_hashes.pop_first();
+        }
+    }
+
+    async fn handle_event(&self, event: &BridgeEvent) -> anyhow::Result<()> {
+        let meta = &event.meta;
+        match &event.payload {
+            TransactionEvent::Mint(mint) => {
+                self.handler
+                    .handle_mint(&MintEvent {
+                        meta: meta.clone(),
+                        to: mint.to.clone(),
+                        value: mint.value,
+                    })
+                    .await?;
+            }
+            TransactionEvent::Burn(burn) => {
+                self.handler
+                    .handle_burn(&BurnEvent {
+                        meta: meta.clone(),
+                        from: burn.from.clone(),
+                        btc_addr: burn.btc_addr.clone(),
+                        value: burn.value,
+                        fee_rate: burn.fee_rate,
+                        operator_id: burn.operator_id,
+                    })
+                    .await?;
+            }
+            TransactionEvent::Contract(contract_event) => {
+                self.handler
+                    .handle_contract_event(meta, contract_event)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn processed_height(&self) -> u64 {
+        self.last_processed_height
+    }
+
+    pub async fn spec_version(&self) -> anyhow::Result<SpecVersion> {
+        self.query_client.spec_version().await
+    }
+
+    pub async fn latest_block_number(&self) -> anyhow::Result<u64> {
+        self.query_client
+            .block_number()
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get latest block number: {}", e))
+    }
+}
+
+/// Where [`EventMonitor::backfill`] stands, reported after every batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackfillProgress {
+    /// Height processed when the backfill started
+    pub start_height: u64,
+    pub target_height: u64,
+    pub processed_height: u64,
+    /// Events passed to the handler so far
+    pub events: usize,
+}
+
+impl BackfillProgress {
+    /// Share of the range processed, from 0 to 1.
+    pub fn fraction(&self) -> f64 {
+        let total = self.target_height.saturating_sub(self.start_height);
+        if total == 0 {
+            return 1.0;
+        }
+        (self.processed_height - self.start_height) as f64 / total as f64
+    }
+}
+
+/// Where an event was emitted.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventMeta {
+    /// The contract that emitted the event
+    #[serde(default)]
+    pub from_address: Felt,
+    pub block_hash: Felt,
+    pub block_number: u64,
+    pub block_timestamp: u64,
+    pub tx_hash: Felt,
+    /// Position of the transaction in its block. Nodes before v0.9 do not report it, so it is
+    /// looked up in the block's transaction list
+    pub tx_index: Option<u64>,
+    /// Position of the event within its transaction. Nodes before v0.9 do not report it, so it
+    /// is looked up in the transaction receipt and stays the same when the node is upgraded
+    pub event_index: u64,
+}
+
+impl EventMeta {
+    /// Identifies the event across refetches, restarts and node upgrades, e.g. as an idempotency
+    /// key. Transaction hashes are unique, so the hash and event index are enough.
+    pub fn id(&self) -> String {
+        format!("0x{:x}:{}", self.tx_hash, self.event_index)
+    }
+
+    pub fn tx_hash_hex(&self) -> String {
+        format!("0x{:x}", self.tx_hash)
+    }
+
+    /// Where a located event (see [`fetch_events`] and [`order_events`]) was emitted, with the
+    /// hash and timestamp of its block taken from `header` where the event lacks them.
+    pub(crate) fn locate(
+        event: &EmittedEvent,
+        header: Option<&BlockHeader>,
+    ) -> anyhow::Result<Self> {
+        let block_number = event.block_number.unwrap_or(0);
+        let block_hash = event
+            .block_hash
+            .or(header.and_then(|h| h.block_hash))
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Block {block_number} of transaction 0x{:x} has no hash",
+                    event.transaction_hash
+                )
+            })?;
+        let event_index = event.event_index.ok_or_else(|| {
+            anyhow::anyhow!(
+                "Event of transaction 0x{:x} has no index",
+                event.transaction_hash
+            )
+        })?;
+        Ok(Self {
+            from_address: event.from_address,
+            block_hash,
+            block_number,
+            block_timestamp: header.map_or(0, |h| h.timestamp),
+            tx_hash: event.transaction_hash,
+            tx_index: event.transaction_index,
+            event_index,
+        })
+    }
+}
+
+/// A decoded bridge event with its position in the chain.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BridgeEvent {
+    pub meta: EventMeta,
+    pub payload: TransactionEvent,
+}
+
+/// Events of one batch of blocks, fetched but not checkpointed yet.
+struct Batch {
+    from_height: u64,
+    to_height: u64,
+    events: Vec<BridgeEvent>,
+    headers: HashMap<u64, BlockHeader>,
+}
+
+enum Step {
+    Batch(Batch),
+    RolledBack,
+    Idle,
+}
+
+struct EventStream {
+    monitor: EventMonitor,
+    end_block: u64,
+    queue: VecDeque<BridgeEvent>,
+    uncommitted: Option<Batch>,
+    delay: Option<Duration>,
+    idle_polls: u32,
+    failures: u32,
+}
+
+impl EventStream {
+    async fn next_event(&mut self) -> anyhow::Result<BridgeEvent> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Ok(event);
+            }
+            if let Some(delay) = self.delay.take() {
+                tokio::time::sleep(delay).await;
+            }
+
+            let progress = self.advance().await;
+            let config = &self.monitor.config;
+            match progress {
+                Ok(true) => {
+                    self.idle_polls = 0;
+                    self.failures = 0;
+                }
+                Ok(false) => {
+                    self.failures = 0;
+                    self.idle_polls += 1;
+                    self.delay = Some(config.poll_backoff.backoff(self.idle_polls));
+                }
+                Err(e) => {
+                    self.failures += 1;
+                    self.delay = Some(config.error_backoff.backoff(self.failures));
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    /// Checkpoints the drained batch and queues the events of the next one. Returns whether the
+    /// monitor made progress.
+    async fn advance(&mut self) -> anyhow::Result<bool> {
+        if let Some(batch) = &self.uncommitted {
+            self.monitor.commit_batch(batch).await?;
+            self.uncommitted = None;
+        }
+        match self.monitor.step(&mut self.end_block).await? {
+            Step::Batch(mut batch) => {
+                self.queue.extend(std::mem::take(&mut batch.events));
+                self.uncommitted = Some(batch);
+                Ok(true)
+            }
+            Step::RolledBack => Ok(true),
+            Step::Idle => Ok(false),
+        }
+    }
+}
+
+/// Fetches the events the contracts emitted in blocks `from_height..=to_height`, one contract at a
+/// time concurrently, in chain order.
+pub(crate) async fn fetch_events(
+    query_client: &QueryClient,
+    contracts: &[WatchedContract],
+    from_height: u64,
+    to_height: u64,
+    chunk_size: u64,
+) -> anyhow::Result<Vec<EmittedEvent>> {
+    let pages = contracts.iter().map(|contract| {
+        fetch_contract_events(query_client, contract, from_height, to_height, chunk_size)
+    });
+    let mut events: Vec<EmittedEvent> = futures::future::try_join_all(pages)
+        .await?
+        .into_iter()
+        .flatten()
+        .collect();
+    locate_events(query_client, &mut events).await?;
+    Ok(events)
+}
+
+/// Fills in the event indexes nodes before v0.9 do not report, from the position of each event
+/// in its transaction's receipt, and sorts the events by position.
+async fn locate_events(
+    query_client: &QueryClient,
+    events: &mut [EmittedEvent],
+) -> anyhow::Result<()> {
+    let tx_hashes: Vec<String> = events
+        .iter()
+        .filter(|e| e.event_index.is_none())
+        .map(|e| format!("0x{:x}", e.transaction_hash))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if !tx_hashes.is_empty() {
+        let tx_hashes: Vec<&str> = tx_hashes.iter().map(String::as_str).collect();
+        let mut receipts = HashMap::new();
+        for receipt in query_client.get_receipts(&tx_hashes).await? {
+            let receipt = receipt?;
+            let located = vec![false; receipt.events.len()];
+            receipts.insert(receipt.transaction_hash, (receipt.events, located));
+        }
+        for event in events.iter_mut().filter(|e| e.event_index.is_none()) {
+            let (receipt_events, located) =
+                receipts.get_mut(&event.transaction_hash).ok_or_else(|| {
+                    anyhow::anyhow!("No receipt for transaction 0x{:x}", event.transaction_hash)
+                })?;
+            // Identical events of a transaction are matched in order
+            let index = (0..receipt_events.len())
+                .find(|&i| {
+                    let e = &receipt_events[i];
+                    !located[i]
+                        && e.from_address == event.from_address
+                        && e.keys == event.keys
+                        && e.data == event.data
+                })
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Event of transaction 0x{:x} is missing from its receipt",
+                        event.transaction_hash
+                    )
+                })?;
+            located[index] = true;
+            event.event_index = Some(index as u64);
+        }
+    }
+    sort_events(events);
+    Ok(())
+}
+
+/// Chain order, as far as the events carry their positions.
+fn sort_events(events: &mut [EmittedEvent]) {
+    events.sort_by_key(|e| (e.block_number, e.transaction_index, e.event_index));
+}
+
+/// Fills in the transaction indexes nodes before v0.9 do not report, from the transaction lists
+/// of `headers`, and sorts the events by position.
+pub(crate) fn order_events(
+    events: &mut [EmittedEvent],
+    headers: &HashMap<u64, BlockHeader>,
+) -> anyhow::Result<()> {
+    for event in events.iter_mut().filter(|e| e.transaction_index.is_none()) {
+        let block_number = event.block_number.unwrap_or(0);
+        let tx_index = headers
+            .get(&block_number)
+            .and_then(|h| {
+                h.transactions
+                    .iter()
+                    .position(|tx| *tx == event.transaction_hash)
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Transaction 0x{:x} is missing from block {block_number}",
+                    event.transaction_hash
+                )
+            })?;
+        event.transaction_index = Some(tx_index as u64);
+    }
+    sort_events(events);
+    Ok(())
+}
+
+/// Fetches every page of the events of `contract` in blocks `from_height..=to_height`.
+async fn fetch_contract_events(
+    query_client: &QueryClient,
+    contract: &WatchedContract,
+    from_height: u64,
+    to_height: u64,
+    chunk_size: u64,
+) -> anyhow::Result<Vec<EmittedEvent>> {
+    let mut events = vec![];
+    let mut continuation_token = None;
+
+    loop {
+        let response = query_client
+            .get_events(
+                EventFilter {
+                    from_block: Some(BlockId::Number(from_height)),
+                    to_block: Some(BlockId::Number(to_height)),
+                    address: Some(contract.address),
+                    keys: Some(vec![contract.events.selectors()]),
+                },
+                continuation_token,
+                chunk_size,
+            )
+            .await?;
+        events.extend(response.events);
+
+        // Update continuation token for next iteration
+        continuation_token = response.continuation_token;
+
+        // If no continuation token, we've got all events for this batch
+        if continuation_token.is_none() {
+            return Ok(events);
+        }
+    }
+}
+
+/// Whether the event's block hash, when the node reports one, matches the header of its block.
+fn in_block(event: &EmittedEvent, headers: &HashMap<u64, BlockHeader>) -> bool {
+    match (
+        event.block_hash,
+        event.block_number.and_then(|n| headers.get(&n)),
+    ) {
+        (Some(hash), Some(header)) => header.block_hash == Some(hash),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+use crate::test_utils::{MockRpc, chain_block, fixture_events, receipt_of, rpc_fixture};
+
+#[cfg(test)]
+#[derive(Default)]
+struct RecordingHandler {
+    events: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    // Number of upcoming burn calls that fail
+    burn_failures: std::sync::atomic::AtomicU32,
+}
+
+#[cfg(test)]
+#[async_trait]
+impl EventHandler for RecordingHandler {
+    async fn handle_mint(&self, event: &MintEvent) -> anyhow::Result<()> {
+        self.events.lock().unwrap().push(format!(
+            "mint {} {} {} {}",
+            event.meta.block_number, event.meta.block_timestamp, event.to, event.value
+        ));
+        Ok(())
+    }
+
+    async fn handle_burn(&self, event: &BurnEvent) -> anyhow::Result<()> {
+        use std::sync::atomic::Ordering;
+        if self.burn_failures.load(Ordering::SeqCst) > 0 {
+            self.burn_failures.fetch_sub(1, Ordering::SeqCst);
+            anyhow::bail!("Burn handler failed");
+        }
+        self.events.lock().unwrap().push(format!(
+            "burn {} {} {} {} {} {} {}",
+            event.meta.block_number,
+            event.meta.block_timestamp,
+            event.from,
+            event.btc_addr,
+            event.value,
+            event.fee_rate,
+            event.operator_id
+        ));
+        Ok(())
+    }
+
+    async fn handle_unconfirmed(
+        &self,
+        _block_number: Option<u64>,
+        tx_hash: &str,
+        event: &TransactionEvent,
+    ) -> anyhow::Result<()> {
+        let kind = match event {
+            TransactionEvent::Mint(_) => "mint",
+            TransactionEvent::Burn(_) => "burn",
+            TransactionEvent::Contract(_) => "contract",
+        };
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("unconfirmed {kind} {tx_hash}"));
+        Ok(())
+    }
+
+    async fn handle_contract_event(
+        &self,
+        meta: &EventMeta,
+        event: &ContractEvent,
+    ) -> anyhow::Result<()> {
+        self.events.lock().unwrap().push(format!(
+            "{} {} {}",
+            event.name, meta.block_number, event.fields
+        ));
+        Ok(())
+    }
+
+    async fn on_reorg(&self, from_height: u64) -> anyhow::Result<()> {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("reorg {from_height}"));
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_monitor_delivers_events_with_timestamps() {
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), |_, _| None).await;
+
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    );
+    monitor.process().await.unwrap();
+
+    assert_eq!(
+        monitor.processed_height(),
+        890900 - MonitorConfig::default().confirmed_blocks
+    );
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "mint 890870 1748951234 0x72b128ce0273e453e21b2d96a94bc72f5c297fcddae1a537f17769b4aaea80 500000",
+            "burn 890870 1748951234 0x72b128ce0273e453e21b2d96a94bc72f5c297fcddae1a537f17769b4aaea80 bcrt1phcnl4zcl2fu047pv4wx6y058v8u0n02at6lthvm7pcf2wrvjm5tqatn90k 500000 5 1",
+        ]
+    );
+    // Besides the two batch boundaries, the block both events share is fetched once
+    assert_eq!(
+        rpc.calls()
+            .iter()
+            .filter(|m| *m == "starknet_getBlockWithTxHashes")
+            .count(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn test_monitor_resumes_from_checkpoint() {
+    use crate::checkpoint::FileCheckpointStore;
+
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), |_, _| None).await;
+    let path = std::env::temp_dir().join(format!("checkpoint-{}", rand::random::<u64>()));
+    FileCheckpointStore::new(&path)
+        .save(Checkpoint::new(890860, None))
+        .await
+        .unwrap();
+    let monitor = |fail_burns| {
+        let handler = RecordingHandler {
+            burn_failures: if fail_burns { u32::MAX } else { 0 }.into(),
+            ..Default::default()
+        };
+        EventMonitor::new(
+            crate::test_utils::BRIDGE_ADDRESS,
+            &rpc.url,
+            Box::new(handler),
+            0,
+        )
+        .with_checkpoint_store(Box::new(FileCheckpointStore::new(&path)))
+    };
+
+    // A failing handler halts the batch and leaves the checkpoint where it was
+    let mut halting = monitor(true).with_config(MonitorConfig {
+        on_handler_failure: FailureAction::Halt,
+        ..Default::default()
+    });
+    assert!(halting.process().await.is_err());
+    assert_eq!(halting.processed_height(), 890860);
+    assert_eq!(
+        FileCheckpointStore::new(&path)
+            .load()
+            .await
+            .unwrap()
+            .map(|c| c.height),
+        Some(890860)
+    );
+
+    // A skipped failure lets progress be persisted
+    let mut skipping = monitor(true).with_config(MonitorConfig {
+        on_handler_failure: FailureAction::Skip,
+        ..Default::default()
+    });
+    skipping.process().await.unwrap();
+    assert_eq!(
+        FileCheckpointStore::new(&path)
+            .load()
+            .await
+            .unwrap()
+            .map(|c| c.height),
+        Some(890894)
+    );
+
+    // A restarted monitor picks up from the saved height and has nothing left to do
+    let calls_before = rpc.calls().len();
+    let mut restarted = monitor(false);
+    restarted.process().await.unwrap();
+    assert_eq!(restarted.processed_height(), 890894);
+    assert!(
+        !rpc.calls()[calls_before..]
+            .iter()
+            .any(|m| m == "starknet_getEvents")
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+/// A node whose blocks from 890870 on (the one with the fixture events) get new hashes once
+/// `forked` is set.
+#[cfg(test)]
+async fn forking_node(
+    head: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    forked: std::sync::Arc<std::sync::atomic::AtomicBool>,
+) -> MockRpc {
+    use std::sync::atomic::Ordering;
+
+    let fixture = rpc_fixture();
+    let block_hash = move |n: u64| {
+        format!(
+            "0x{:x}",
+            n * 16 + (forked.load(Ordering::SeqCst) && n >= 890870) as u64
+        )
+    };
+    let node_fixture = fixture.clone();
+    MockRpc::from_fixture(&fixture, move |method, params| match method {
+        "starknet_blockNumber" => Some(Ok(serde_json::json!(head.load(Ordering::SeqCst)))),
+        "starknet_getEvents" if params["filter"]["continuation_token"].is_null() => {
+            let filter = &params["filter"];
+            let from = filter["from_block"]["block_number"].as_u64().unwrap();
+            let to = filter["to_block"]["block_number"].as_u64().unwrap();
+            (from..=to)
+                .contains(&890870)
+                .then(|| Ok(fixture_events(&node_fixture, &block_hash(890870))))
+        }
+        "starknet_getBlockWithTxHashes" => {
+            let n = params["block_id"]["block_number"].as_u64().unwrap();
+            let mut block = chain_block(&node_fixture, n);
+            block["block_hash"] = block_hash(n).into();
+            block["parent_hash"] = block_hash(n - 1).into();
+            Some(Ok(block))
+        }
+        _ => None,
+    })
+    .await
+}
+
+#[tokio::test]
+async fn test_monitor_rolls_back_reorganized_blocks() {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    };
+
+    let head = Arc::new(AtomicU64::new(890900));
+    let forked = Arc::new(AtomicBool::new(false));
+    let rpc = forking_node(head.clone(), forked.clone()).await;
+
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    );
+    monitor.process().await.unwrap();
+    assert_eq!(events.lock().unwrap().len(), 2);
+
+    // The chain forks below the processed height; the events of block 890870 are rolled back
+    // and delivered again from the new chain
+    forked.store(true, Ordering::SeqCst);
+    head.store(890950, Ordering::SeqCst);
+    monitor.process().await.unwrap();
+    assert_eq!(monitor.processed_height(), 890944);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 5);
+    assert_eq!(events[2], "reorg 890862");
+    assert_eq!(events[3], events[0]);
+}
+
+#[tokio::test]
+async fn test_monitor_detects_reorg_across_restart() {
+    use crate::checkpoint::FileCheckpointStore;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    };
+
+    let head = Arc::new(AtomicU64::new(890900));
+    let forked = Arc::new(AtomicBool::new(false));
+    let rpc = forking_node(head.clone(), forked.clone()).await;
+    let path = std::env::temp_dir().join(format!("checkpoint-{}", rand::random::<u64>()));
+    let monitor = |handler: RecordingHandler| {
+        EventMonitor::new(
+            crate::test_utils::BRIDGE_ADDRESS,
+            &rpc.url,
+            Box::new(handler),
+            890860,
+        )
+        .with_checkpoint_store(Box::new(FileCheckpointStore::new(&path)))
+    };
+    monitor(RecordingHandler::default())
+        .process()
+        .await
+        .unwrap();
+
+    // The chain forks while the monitor is down; only the hash of the saved block is known, so
+    // the restarted one rolls back `reorg_depth` blocks below it and delivers block 890870 again
+    forked.store(true, Ordering::SeqCst);
+    head.store(890950, Ordering::SeqCst);
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut restarted = monitor(handler).with_config(MonitorConfig {
+        reorg_depth: 30,
+        ..MonitorConfig::default()
+    });
+    restarted.process().await.unwrap();
+    assert_eq!(restarted.processed_height(), 890944);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0], "reorg 890865");
+    assert!(events[1].starts_with("mint 890870"), "{}", events[1]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_monitor_finality_modes() {
+    let fixture = rpc_fixture();
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+    // Head at 890900, L1 has accepted everything up to 890880, the fixture events are both in
+    // block 890870 and in the pending block
+    let head = Arc::new(AtomicU64::new(890900));
+    let pending = Arc::new(AtomicBool::new(true));
+    let (node_head, node_pending) = (head.clone(), pending.clone());
+    let node_fixture = fixture.clone();
+    let rpc = MockRpc::from_fixture(&fixture, move |method, params| match method {
+        "starknet_blockNumber" => Some(Ok(serde_json::json!(node_head.load(Ordering::SeqCst)))),
+        "starknet_getEvents" if params["filter"]["from_block"] == "pending" => {
+            let mut page = serde_json::json!({"events": []});
+            if node_pending.load(Ordering::SeqCst) {
+                page = node_fixture["events"].clone();
+                page["continuation_token"] = serde_json::Value::Null;
+                // Pending events carry no block before v0.9
+                for event in page["events"].as_array_mut().unwrap() {
+                    let event = event.as_object_mut().unwrap();
+                    event.remove("block_number");
+                    event.remove("block_hash");
+                }
+            }
+            Some(Ok(page))
+        }
+        "starknet_getBlockWithTxHashes" => {
+            let n = params["block_id"]["block_number"].as_u64().unwrap();
+            let mut block = chain_block(&node_fixture, n);
+            block["status"] = if n <= 890880 {
+                "ACCEPTED_ON_L1"
+            } else {
+                "ACCEPTED_ON_L2"
+            }
+            .into();
+            Some(Ok(block))
+        }
+        _ => None,
+    })
+    .await;
+    let monitor = |finality, height| {
+        let handler = RecordingHandler::default();
+        let events = handler.events.clone();
+        let monitor = EventMonitor::new(
+            crate::test_utils::BRIDGE_ADDRESS,
+            &rpc.url,
+            Box::new(handler),
+            height,
+        )
+        .with_config(MonitorConfig {
+            finality,
+            ..Default::default()
+        });
+        (monitor, events)
+    };
+
+    let (mut l1, events) = monitor(FinalityMode::AcceptedOnL1, 890860);
+    l1.process().await.unwrap();
+    assert_eq!(l1.processed_height(), 890880);
+    assert_eq!(events.lock().unwrap().len(), 2);
+
+    // Already caught up, so only the pending events are delivered, and only once
+    let (mut pre_confirmed, events) = monitor(FinalityMode::PreConfirmed, 890894);
+    pre_confirmed.process().await.unwrap();
+    pre_confirmed.process().await.unwrap();
+    assert_eq!(pre_confirmed.processed_height(), 890894);
+    {
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events[0].starts_with("unconfirmed mint"));
+        assert!(events[1].starts_with("unconfirmed burn"));
+    }
+
+    // The transactions are dropped; they are forgotten once the pending block they were in is processed
+    pending.store(false, Ordering::SeqCst);
+    head.store(890906, Ordering::SeqCst);
+    pre_confirmed.process().await.unwrap();
+    assert_eq!(pre_confirmed.unconfirmed_delivered.len(), 2);
+    head.store(890907, Ordering::SeqCst);
+    pre_confirmed.process().await.unwrap();
+    assert!(pre_confirmed.unconfirmed_delivered.is_empty());
+    assert_eq!(events.lock().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_run_finishes_batch_before_shutdown() {
+    use crate::checkpoint::FileCheckpointStore;
+    use std::time::Instant;
+
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), |_, _| None).await;
+    let path = std::env::temp_dir().join(format!("checkpoint-{}", rand::random::<u64>()));
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    )
+    .with_config(MonitorConfig {
+        blocks_per_batch: 10,
+        ..Default::default()
+    })
+    .with_checkpoint_store(Box::new(FileCheckpointStore::new(&path)));
+
+    // Shutdown is requested while the first batch is being handled
+    let seen = events.clone();
+    monitor
+        .run(async move {
+            while seen.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    assert_eq!(events.lock().unwrap().len(), 2);
+    assert_eq!(monitor.processed_height(), 890870);
+    assert_eq!(
+        FileCheckpointStore::new(&path)
+            .load()
+            .await
+            .unwrap()
+            .map(|c| c.height),
+        Some(890870)
+    );
+
+    // Once caught up, a shutdown during the idle sleep returns right away
+    let started = Instant::now();
+    monitor
+        .run(tokio::time::sleep(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    assert_eq!(monitor.processed_height(), 890894);
+    assert!(started.elapsed() < MonitorConfig::default().poll_backoff.initial_backoff);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_poll_backoff_doubles_up_to_max() {
+    let poll = MonitorConfig::default().poll_backoff;
+    assert_eq!(poll.backoff(1), Duration::from_secs(2));
+    assert_eq!(poll.backoff(3), Duration::from_secs(8));
+    assert_eq!(poll.backoff(40), poll.max_backoff);
+}
+
+#[tokio::test]
+async fn test_stream_checkpoints_after_batch_is_drained() {
+    use crate::checkpoint::FileCheckpointStore;
+    use futures::StreamExt;
+
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), |_, _| None).await;
+    let path = std::env::temp_dir().join(format!("checkpoint-{}", rand::random::<u64>()));
+    let stream = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(RecordingHandler::default()),
+        890860,
+    )
+    .with_checkpoint_store(Box::new(FileCheckpointStore::new(&path)))
+    .into_stream();
+    let mut stream = std::pin::pin!(stream);
+
+    let mint = stream.next().await.unwrap().unwrap();
+    assert_eq!(mint.meta.block_number, 890870);
+    assert_eq!(mint.meta.block_timestamp, 1748951234);
+    assert!(matches!(mint.payload, TransactionEvent::Mint(_)));
+    let burn = stream.next().await.unwrap().unwrap();
+    assert!(
+        matches!(burn.payload, TransactionEvent::Burn(ref b) if b.value == BtcAmount::from_sats(500000))
+    );
+
+    // Not checkpointed until the consumer comes back for more
+    let store = FileCheckpointStore::new(&path);
+    assert_eq!(store.load().await.unwrap(), None);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(100), stream.next())
+            .await
+            .is_err()
+    );
+    assert_eq!(store.load().await.unwrap().map(|c| c.height), Some(890894));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_run_ws_follows_subscription_and_fills_gaps() {
+    use crate::test_utils::MockWs;
+    use serde_json::json;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    };
+
+    let fixture = rpc_fixture();
+    let head = Arc::new(AtomicU64::new(890880));
+    let node_head = head.clone();
+    let rpc = MockRpc::from_fixture(&fixture, move |method, _| match method {
+        "starknet_blockNumber" => Some(Ok(json!(node_head.load(Ordering::SeqCst)))),
+        _ => None,
+    })
+    .await;
+    let ws = MockWs::start(|method, _| match method {
+        "starknet_subscribeNewHeads" => Ok(json!("1")),
+        "starknet_subscribeEvents" => Ok(json!("2")),
+        _ => Err(json!({"code": -32601, "message": "Method not found"})),
+    })
+    .await;
+
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    )
+    .with_config(MonitorConfig {
+        error_backoff: RetryPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        },
+        ..Default::default()
+    });
+
+    let subscriptions = || {
+        ws.calls()
+            .iter()
+            .filter(|m| *m == "starknet_subscribeEvents")
+            .count()
+    };
+    let handled = events.clone();
+    let scenario = async {
+        while subscriptions() < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        // The blocks up to 890874 were caught up by polling; a mint in 890878 now arrives over
+        // the subscription and is final once the head reaches 890884
+        let mut mint = fixture["events"]["events"][0].clone();
+        mint["block_hash"] = json!("0xd97fe");
+        mint["block_number"] = json!(890878);
+        head.store(890884, Ordering::SeqCst);
+        for (method, result) in [
+            ("starknet_subscriptionEvents", mint),
+            (
+                "starknet_subscriptionNewHeads",
+                json!({"block_hash": "0xd97fc", "parent_hash": "0xd97fb", "block_number": 890884, "timestamp": 1748951234}),
+            ),
+        ] {
+            ws.notify
+                .send(json!({"jsonrpc": "2.0", "method": method, "params": {"subscription_id": "1", "result": result}}))
+                .unwrap();
+        }
+        while handled.lock().unwrap().len() < 3 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // Drop the connection; the blocks produced meanwhile are polled before resubscribing
+        head.store(890890, Ordering::SeqCst);
+        ws.notify.send(serde_json::Value::Null).unwrap();
+        while subscriptions() < 2 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    };
+    monitor.run_ws(&ws.url, scenario).await.unwrap();
+
+    assert_eq!(monitor.processed_height(), 890884);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 3);
+    assert!(events[2].starts_with("mint 890878"));
+    // Two pages for the initial catch-up and one for the gap after reconnecting; the subscribed
+    // blocks were never polled
+    assert_eq!(
+        rpc.calls()
+            .iter()
+            .filter(|m| *m == "starknet_getEvents")
+            .count(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn test_identical_events_of_one_transaction_get_distinct_ids() {
+    use futures::StreamExt;
+
+    let fixture = rpc_fixture();
+    // A multicall minting the same amount to the same recipient twice, after paying its fee
+    let mint = fixture["events"]["events"][0].clone();
+    let mut fee = mint.clone();
+    fee["from_address"] = fixture["transaction_receipt"]["events"][1]["from_address"].clone();
+    let block = fixture["block"].clone();
+    let rpc = MockRpc::from_fixture(&fixture, move |method, params| match method {
+        "starknet_getEvents" => Some(Ok(serde_json::json!({"events": [mint, mint]}))),
+        "starknet_getTransactionReceipt" => Some(Ok(receipt_of(
+            &serde_json::json!([fee, mint, mint]),
+            params,
+        ))),
+        "starknet_getBlockWithTxHashes" => Some(Ok(block.clone())),
+        _ => None,
+    })
+    .await;
+    let stream = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(RecordingHandler::default()),
+        890860,
+    )
+    .into_stream();
+    let mut stream = std::pin::pin!(stream);
+
+    let first = stream.next().await.unwrap().unwrap().meta;
+    let second = stream.next().await.unwrap().unwrap().meta;
+    // Positions in the receipt, as v0.9 nodes report them
+    assert_eq!(first.tx_hash, second.tx_hash);
+    assert_eq!((first.event_index, second.event_index), (1, 2));
+    assert_ne!(first.id(), second.id());
+    assert_eq!(
+        first.block_hash,
+        Felt::from_hex("0x4c3e9bfa8e4c2f0a1d6b7c8e9f0a1b2c3d4e5f60718293a4b5c6d7e8f9012ab")
+            .unwrap()
+    );
+    assert_eq!(first.tx_index, Some(0));
+}
+
+#[tokio::test]
+async fn test_handler_failures_are_retried_or_dead_lettered() {
+    use crate::dead_letter::{DeadLetterSink, FileDeadLetterSink};
+
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), |_, _| None).await;
+    let monitor = |burn_failures: u32, config| {
+        let handler = RecordingHandler {
+            burn_failures: burn_failures.into(),
+            ..Default::default()
+        };
+        let events = handler.events.clone();
+        let monitor = EventMonitor::new(
+            crate::test_utils::BRIDGE_ADDRESS,
+            &rpc.url,
+            Box::new(handler),
+            890860,
+        )
+        .with_config(config);
+        (monitor, events)
+    };
+
+    // Two transient failures are absorbed by three attempts
+    let (mut retrying, events) = monitor(
+        2,
+        MonitorConfig {
+            handler_retry: RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            },
+            on_handler_failure: FailureAction::Halt,
+            ..Default::default()
+        },
+    );
+    retrying.process().await.unwrap();
+    assert_eq!(events.lock().unwrap().len(), 2);
+
+    // Without retries the burn is dead-lettered, the monitor moves on and the replay delivers it
+    let path = std::env::temp_dir().join(format!("dead-letters-{}", rand::random::<u64>()));
+    let sink = Arc::new(FileDeadLetterSink::new(&path));
+    let (mut dead_lettering, events) = monitor(
+        1,
+        MonitorConfig {
+            handler_retry: RetryPolicy::none(),
+            on_handler_failure: FailureAction::DeadLetter(sink.clone()),
+            ..Default::default()
+        },
+    );
+    dead_lettering.process().await.unwrap();
+    assert_eq!(dead_lettering.processed_height(), 890894);
+    assert_eq!(events.lock().unwrap().len(), 1);
+    let letters = sink.pending().await.unwrap();
+    assert_eq!(letters.len(), 1);
+    assert_eq!(letters[0].error, "Burn handler failed");
+
+    assert_eq!(dead_lettering.replay_dead_letters().await.unwrap(), 1);
+    assert!(sink.pending().await.unwrap().is_empty());
+    assert!(events.lock().unwrap()[1].starts_with("burn 890870"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_undecodable_events_are_not_dropped() {
+    use crate::dead_letter::{DeadLetterSink, FileDeadLetterSink};
+
+    // A burn missing most of its fields
+    let mut events = fixture_events(&rpc_fixture(), "0xd97f6")["events"].clone();
+    events[1]["data"] = serde_json::json!(["0x1"]);
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), move |method, params| match method {
+        "starknet_getEvents" => Some(Ok(serde_json::json!({ "events": events }))),
+        "starknet_getTransactionReceipt" => Some(Ok(receipt_of(&events, params))),
+        _ => None,
+    })
+    .await;
+    let monitor = |on_handler_failure| {
+        let handler = RecordingHandler::default();
+        let events = handler.events.clone();
+        let monitor = EventMonitor::new(
+            crate::test_utils::BRIDGE_ADDRESS,
+            &rpc.url,
+            Box::new(handler),
+            890860,
+        )
+        .with_config(MonitorConfig {
+            on_handler_failure,
+            ..Default::default()
+        });
+        (monitor, events)
+    };
+
+    let (mut halting, _) = monitor(FailureAction::Halt);
+    let error = halting.process().await.unwrap_err();
+    assert!(format!("{error:#}").contains("Failed to decode the event"));
+    assert_eq!(halting.processed_height(), 890860);
+
+    let path = std::env::temp_dir().join(format!("dead-letters-{}", rand::random::<u64>()));
+    let sink = Arc::new(FileDeadLetterSink::new(&path));
+    let (mut dead_lettering, events) = monitor(FailureAction::DeadLetter(sink.clone()));
+    dead_lettering.process().await.unwrap();
+    assert_eq!(dead_lettering.processed_height(), 890894);
+    assert_eq!(events.lock().unwrap().len(), 1);
+    let letters = sink.pending().await.unwrap();
+    assert_eq!(letters.len(), 1);
+    assert!(matches!(
+        &letters[0].event.payload,
+        TransactionEvent::Contract(raw) if raw.name == UNDECODED_EVENT
+    ));
+    // Replays decode the raw event again, which still fails
+    assert_eq!(dead_lettering.replay_dead_letters().await.unwrap(), 0);
+    assert_eq!(sink.pending().await.unwrap().len(), 1);
+    assert_eq!(events.lock().unwrap().len(), 1);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_cached_blocks_are_checked_against_event_block_hashes() {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    };
+
+    let fixture = rpc_fixture();
+    // Hashes the node reports for block 890870 and in its events, changed by the test
+    let header_hash = Arc::new(AtomicU64::new(0xa));
+    let event_hash = Arc::new(AtomicU64::new(0xa));
+    let (node_header_hash, node_event_hash) = (header_hash.clone(), event_hash.clone());
+    let node_fixture = fixture.clone();
+    let rpc = MockRpc::from_fixture(&fixture, move |method, params| match method {
+        "starknet_getEvents" if params["filter"]["continuation_token"].is_null() => {
+            Some(Ok(fixture_events(
+                &node_fixture,
+                &format!("0x{:x}", node_event_hash.load(Ordering::SeqCst)),
+            )))
+        }
+        "starknet_getBlockWithTxHashes" if params["block_id"]["block_number"] == 890870 => {
+            let mut block = chain_block(&node_fixture, 890870);
+            block["block_hash"] = format!("0x{:x}", node_header_hash.load(Ordering::SeqCst)).into();
+            Some(Ok(block))
+        }
+        _ => None,
+    })
+    .await;
+    let block_fetches = || {
+        rpc.calls()
+            .iter()
+            .filter(|m| *m == "starknet_getBlockWithTxHashes")
+            .count()
+    };
+
+    let handler = RecordingHandler {
+        burn_failures: 1.into(),
+        ..Default::default()
+    };
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    )
+    .with_config(MonitorConfig {
+        handler_retry: RetryPolicy::none(),
+        ..Default::default()
+    });
+    assert!(monitor.process().await.is_err());
+    assert_eq!(block_fetches(), 3);
+
+    // Retrying the batch only fetches its first block again
+    monitor.process().await.unwrap();
+    assert_eq!(block_fetches(), 4);
+    assert_eq!(events.lock().unwrap().len(), 3);
+
+    // Block 890870 was replaced: the cached header no longer matches the events and is refetched
+    let mut monitor = monitor.with_config(MonitorConfig::default());
+    monitor.last_processed_height = 890860;
+    header_hash.store(0xb, Ordering::SeqCst);
+    event_hash.store(0xb, Ordering::SeqCst);
+    monitor.process().await.unwrap();
+    assert_eq!(block_fetches(), 6);
+
+    // Events of a block the node does not serve are never delivered
+    monitor.last_processed_height = 890860;
+    event_hash.store(0xc, Ordering::SeqCst);
+    assert!(monitor.process().await.is_err());
+    assert_eq!(events.lock().unwrap().len(), 5);
+}
+
+#[tokio::test]
+async fn test_backfill_delivers_concurrently_fetched_batches_in_order() {
+    let fixture = rpc_fixture();
+    // The fixture events, emitted again in block 890885
+    let node_fixture = fixture.clone();
+    let rpc = MockRpc::from_fixture(&fixture, move |method, params| match method {
+        "starknet_getEvents" => {
+            let filter = &params["filter"];
+            let from = filter["from_block"]["block_number"].as_u64().unwrap();
+            let to = filter["to_block"]["block_number"].as_u64().unwrap();
+            let mut page = serde_json::json!({"events": []});
+            for block in [890870u64, 890885] {
+                if (from..=to).contains(&block) && !filter["continuation_token"].is_string() {
+                    page = fixture_events(&node_fixture, &format!("0x{block:x}"));
+                    for event in page["events"].as_array_mut().unwrap() {
+                        event["block_number"] = block.into();
+                    }
+                }
+            }
+            Some(Ok(page))
+        }
+        "starknet_getBlockWithTxHashes" => {
+            let n = params["block_id"]["block_number"].as_u64().unwrap();
+            let mut block = chain_block(&node_fixture, n);
+            block["timestamp"] = n.into();
+            Some(Ok(block))
+        }
+        _ => None,
+    })
+    .await;
+
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    )
+    .with_config(MonitorConfig {
+        blocks_per_batch: 10,
+        backfill_workers: 3,
+        ..Default::default()
+    });
+    let mut reports = vec![];
+    // Capped at the final height
+    monitor
+        .backfill(890950, |progress| reports.push(progress.clone()))
+        .await
+        .unwrap();
+
+    assert_eq!(monitor.processed_height(), 890894);
+    let heights: Vec<u64> = reports.iter().map(|r| r.processed_height).collect();
+    assert_eq!(heights, [890870, 890880, 890890, 890894]);
+    assert_eq!(reports[1].events, 2);
+    assert_eq!(reports[3].events, 4);
+    assert_eq!(reports[3].fraction(), 1.0);
+    let events = events.lock().unwrap();
+    let blocks: Vec<&str> = events
+        .iter()
+        .map(|e| e.split(' ').take(2).collect::<Vec<_>>()[1])
+        .collect();
+    assert_eq!(blocks, ["890870", "890870", "890885", "890885"]);
+    assert!(events[0].starts_with("mint") && events[3].starts_with("burn"));
+}
+
+#[tokio::test]
+async fn test_watched_contracts_share_the_scan() {
+    use serde_json::json;
+
+    const TOKEN: &str = "0x4718f5a0fc34cc1af16a1cdee98ffb20c31f5cd61d6ab07201858f4287c938d";
+    let transfer_selector = starknet::core::utils::get_selector_from_name("Transfer").unwrap();
+    let fixture = rpc_fixture();
+    // The token emits a transfer before and one alongside the bridge events
+    let transfer = |block: u64, value: u64| {
+        json!({
+            "from_address": TOKEN,
+            "keys": [transfer_selector],
+            "data": ["0x1", "0x2", format!("0x{value:x}"), "0x0"],
+            "block_hash": format!("0x{block:x}"),
+            "block_number": block,
+            "transaction_hash": format!("0x{block:x}{value:x}"),
+        })
+    };
+    let transfers = json!([transfer(890865, 7), transfer(890870, 9)]);
+    let mut emitted = fixture["events"]["events"].clone();
+    emitted
+        .as_array_mut()
+        .unwrap()
+        .extend(transfers.as_array().unwrap().iter().cloned());
+    let node_fixture = fixture.clone();
+    let rpc = MockRpc::from_fixture(&fixture, move |method, params| match method {
+        "starknet_getEvents"
+            if params["filter"]["address"] == TOKEN
+                && params["filter"]["continuation_token"].is_null() =>
+        {
+            assert_eq!(params["filter"]["keys"], json!([[transfer_selector]]));
+            Some(Ok(json!({"events": transfers})))
+        }
+        "starknet_getBlockWithTxHashes" => {
+            let n = params["block_id"]["block_number"].as_u64().unwrap();
+            let mut block = chain_block(&node_fixture, n);
+            // The transfers run before the bridge transactions of their block
+            block["transactions"] = json!([
+                format!("0x{n:x}7"),
+                format!("0x{n:x}9"),
+                &node_fixture["block"]["transactions"][0],
+                &node_fixture["block"]["transactions"][1],
+            ]);
+            Some(Ok(block))
+        }
+        "starknet_getTransactionReceipt" => Some(Ok(receipt_of(&emitted, params))),
+        _ => None,
+    })
+    .await;
+
+    let handler = RecordingHandler::default();
+    let events = handler.events.clone();
+    let mut monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        &rpc.url,
+        Box::new(handler),
+        890860,
+    )
+    .watch_contract(TOKEN, &[transfer_selector], |event| {
+        Ok(TransactionEvent::Contract(ContractEvent {
+            name: "Transfer".to_string(),
+            fields: json!({ "value": crate::utils::felt_to_u64(&event.data[2])? }),
+        }))
+    })
+    .unwrap();
+    monitor.process().await.unwrap();
+
+    assert_eq!(monitor.processed_height(), 890894);
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 4);
+    // In chain order across contracts, although nodes before v0.9 report no positions
+    assert_eq!(events[0], r#"Transfer 890865 {"value":7}"#);
+    assert_eq!(events[1], r#"Transfer 890870 {"value":9}"#);
+    assert!(events[2].starts_with("mint 890870"));
+    assert!(events[3].starts_with("burn 890870"));
+}
+
+#[test]
+fn test_event_registry_extends_the_built_in_events() {
+    use serde_json::json;
+
+    let fixture = rpc_fixture();
+    let events: Vec<EmittedEvent> =
+        serde_json::from_value(fixture["events"]["events"].clone()).unwrap();
+    let paused = starknet::core::utils::get_selector_from_name("Paused").unwrap();
+    let mut event = events[0].clone();
+    event.keys = vec![paused];
+    event.data = vec![];
+
+    let monitor = EventMonitor::new(
+        crate::test_utils::BRIDGE_ADDRESS,
+        "http://127.0.0.1:1",
+        Box::new(RecordingHandler::default()),
+        0,
+    )
+    .with_event(paused, |_| {
+        Ok(TransactionEvent::Contract(ContractEvent {
+            name: "Paused".to_string(),
+            fields: json!({}),
+        }))
+    })
+    .unwrap();
+    let registry = &monitor.contracts[0].events;
+    let mut selectors = vec![MINT_EVENT_SELECTOR, BURN_EVENT_SELECTOR, paused];
+    selectors.sort();
+    assert_eq!(registry.selectors(), selectors);
+
+    assert!(matches!(
+        registry.decode(&events[0]).unwrap(),
+        TransactionEvent::Mint(_)
+    ));
+    assert!(matches!(
+        registry.decode(&event).unwrap(),
+        TransactionEvent::Contract(ContractEvent { ref name, .. }) if name == "Paused"
+    ));
+    assert!(EventRegistry::default().decode(&event).is_err());
+
+    // Configuration mistakes are errors
+    assert!(
+        EventRegistry::default()
+            .register(MINT_EVENT_SELECTOR, parse_event)
+            .is_err()
+    );
+    let monitor = monitor
+        .watch_contract_events("0x123", EventRegistry::default())
+        .unwrap();
+    assert!(
+        monitor
+            .watch_contract_events("0x0123", EventRegistry::default())
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn test_channels_and_closures_receive_batches_in_order() {
+    let rpc = MockRpc::from_fixture(&rpc_fixture(), |_, _| None).await;
+    let contract = crate::test_utils::BRIDGE_ADDRESS;
+
+    // Next to another handler, through a fan-out
+    let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+    let recording = RecordingHandler::default();
+    let recorded = recording.events.clone();
+    let handlers: Vec<Box<dyn EventHandler>> = vec![Box::new(recording), Box::new(sender)];
+    let mut monitor = EventMonitor::new(contract, &rpc.url, Box::new(handlers), 890860);
+    monitor.process().await.unwrap();
+    drop(monitor);
+    assert_eq!(recorded.lock().unwrap().len(), 2);
+
+    let mut received = vec![];
+    while let Some(event) = receiver.recv().await {
+        received.push(event);
+    }
+    assert!(matches!(
+        received[0],
+        MonitorEvent::BatchStart {
+            from_height: 890861,
+            to_height: 890894
+        }
+    ));
+    let MonitorEvent::Mint(mint) = &received[1] else {
+        panic!("Expected a mint, got {:?}", received[1]);
+    };
+    assert_eq!(mint.value, BtcAmount::from_sats(500000));
+    assert_eq!(mint.meta.block_number, 890870);
+    let MonitorEvent::Burn(burn) = &received[2] else {
+        panic!("Expected a burn, got {:?}", received[2]);
+    };
+    assert_eq!((burn.fee_rate, burn.operator_id), (5, 1));
+    assert!(matches!(
+        received[3],
+        MonitorEvent::BatchCommit { to_height: 890894 }
+    ));
+    assert_eq!(received.len(), 4);
+
+    // A failing closure fails the batch before it is committed
+    let mut monitor = EventMonitor::new(
+        contract,
+        &rpc.url,
+        Box::new(|event| async move {
+            match event {
+                MonitorEvent::BatchCommit { .. } => anyhow::bail!("Database unavailable"),
+                _ => Ok(()),
+            }
+        }),
+        890860,
+    );
+    assert!(monitor.process().await.is_err());
+    assert_eq!(monitor.processed_height(), 890860);
 }
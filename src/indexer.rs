@@ -0,0 +1,479 @@
+//! Persists the bridge's Mint and Burn events in SQLite and answers the usual questions about
+//! them. Register a [`SqliteIndexer`] as both the monitor's handler and its checkpoint store:
+//! every batch is written in one transaction together with its checkpoint.
+
+use crate::{
+    checkpoint::{self, Checkpoint, CheckpointStore},
+    events::{BurnEvent, EventHandler, EventMeta, MintEvent},
+    types::BtcAmount,
+    utils::with_connection,
+};
+use async_trait::async_trait;
+use rusqlite::{Connection, Row, Transaction, params};
+use starknet::core::types::Felt;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+const CHECKPOINT_NAME: &str = "indexer";
+
+/// Sums of the events in a time range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    pub mints: u64,
+    pub minted: BtcAmount,
+    pub burns: u64,
+    pub burned: BtcAmount,
+}
+
+/// Event handler and checkpoint store keeping the bridge's events in SQLite. Clones share the
+/// database.
+#[derive(Clone)]
+pub struct SqliteIndexer {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // Shared with the blocking threads the handler and checkpoint calls run on
+    conn: Arc<Mutex<Connection>>,
+    // The first block and events of the batch in progress, written when it commits
+    batch: Mutex<Option<(u64, Vec<Indexed>)>>,
+}
+
+enum Indexed {
+    Mint(MintEvent),
+    Burn(BurnEvent),
+}
+
+impl SqliteIndexer {
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    pub fn from_connection(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS blocks (
+                number INTEGER PRIMARY KEY,
+                hash TEXT NOT NULL,
+                timestamp INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS mints (
+                id TEXT PRIMARY KEY,
+                from_address TEXT NOT NULL,
+                block_number INTEGER NOT NULL REFERENCES blocks(number),
+                tx_hash TEXT NOT NULL,
+                tx_index INTEGER,
+                event_index INTEGER NOT NULL,
+                recipient TEXT NOT NULL,
+                value TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS mints_by_recipient ON mints (recipient);
+            CREATE TABLE IF NOT EXISTS burns (
+                id TEXT PRIMARY KEY,
+                from_address TEXT NOT NULL,
+                block_number INTEGER NOT NULL REFERENCES blocks(number),
+                tx_hash TEXT NOT NULL,
+                tx_index INTEGER,
+                event_index INTEGER NOT NULL,
+                sender TEXT NOT NULL,
+                btc_addr TEXT NOT NULL,
+                value TEXT NOT NULL,
+                fee_rate INTEGER NOT NULL,
+                operator_id INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS burns_by_operator ON burns (operator_id);
+            -- Kept apart from burns so a reorg that replays a burn keeps its payout
+            CREATE TABLE IF NOT EXISTS burn_payouts (
+                burn_id TEXT PRIMARY KEY,
+                btc_txid TEXT NOT NULL
+            );",
+        )?;
+        checkpoint::create_checkpoints_table(&conn)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                conn: Arc::new(Mutex::new(conn)),
+                batch: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Mints to `recipient`, oldest first.
+    pub fn mints_by_recipient(&self, recipient: &str) -> anyhow::Result<Vec<MintEvent>> {
+        let recipient = format!("0x{:x}", Felt::from_hex(recipient)?);
+        let conn = self.inner.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT m.from_address, b.hash, m.block_number, b.timestamp, m.tx_hash, m.tx_index,
+                    m.event_index, m.recipient, m.value
+             FROM mints m JOIN blocks b ON b.number = m.block_number
+             WHERE m.recipient = ?1
+             ORDER BY m.block_number, m.tx_index, m.event_index",
+        )?;
+        let mut rows = stmt.query(params![recipient])?;
+        let mut mints = vec![];
+        while let Some(row) = rows.next()? {
+            mints.push(mint_from_row(row)?);
+        }
+        Ok(mints)
+    }
+
+    /// Burns assigned to `operator_id`, oldest first.
+    pub fn burns_by_operator(&self, operator_id: u32) -> anyhow::Result<Vec<BurnEvent>> {
+        self.burns("WHERE b.operator_id = ?1", params![operator_id])
+    }
+
+    /// Burns without a recorded BTC payout, oldest first.
+    pub fn pending_burns(&self) -> anyhow::Result<Vec<BurnEvent>> {
+        self.burns(
+            "WHERE NOT EXISTS (SELECT 1 FROM burn_payouts p WHERE p.burn_id = b.id)",
+            params![],
+        )
+    }
+
+    /// Records that the burn with event id `burn_id` was paid out in BTC transaction
+    /// `btc_txid`.
+    pub fn mark_burn_fulfilled(&self, burn_id: &str, btc_txid: &str) -> anyhow::Result<()> {
+        let conn = self.inner.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO burn_payouts (burn_id, btc_txid) VALUES (?1, ?2)
+             ON CONFLICT(burn_id) DO UPDATE SET btc_txid = excluded.btc_txid",
+            params![burn_id, btc_txid],
+        )?;
+        Ok(())
+    }
+
+    /// Counts and sums of the events in blocks with timestamps `from_timestamp..to_timestamp`.
+    pub fn totals(&self, from_timestamp: u64, to_timestamp: u64) -> anyhow::Result<Totals> {
+        let conn = self.inner.conn.lock().unwrap();
+        let mut totals = Totals::default();
+        for (table, count, sum) in [
+            ("mints", &mut totals.mints, &mut totals.minted),
+            ("burns", &mut totals.burns, &mut totals.burned),
+        ] {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT e.value FROM {table} e JOIN blocks b ON b.number = e.block_number
+                 WHERE b.timestamp >= ?1 AND b.timestamp < ?2"
+            ))?;
+            let values = stmt.query_map(params![from_timestamp, to_timestamp], |row| {
+                row.get::<_, String>(0)
+            })?;
+            for value in values {
+                *count += 1;
+                *sum = sum
+                    .checked_add(parse_amount(&value?)?)
+                    .ok_or_else(|| anyhow::anyhow!("Total of {table} overflows"))?;
+            }
+        }
+        Ok(totals)
+    }
+
+    fn burns(&self, filter: &str, params: impl rusqlite::Params) -> anyhow::Result<Vec<BurnEvent>> {
+        let conn = self.inner.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT b.from_address, k.hash, b.block_number, k.timestamp, b.tx_hash, b.tx_index,
+                    b.event_index, b.sender, b.btc_addr, b.value, b.fee_rate, b.operator_id
+             FROM burns b JOIN blocks k ON k.number = b.block_number
+             {filter}
+             ORDER BY b.block_number, b.tx_index, b.event_index"
+        ))?;
+        let mut rows = stmt.query(params)?;
+        let mut burns = vec![];
+        while let Some(row) = rows.next()? {
+            burns.push(burn_from_row(row)?);
+        }
+        Ok(burns)
+    }
+
+    /// Writes events outside the batch in progress, e.g. dead letter replays, right away.
+    async fn index(&self, event: Indexed, block_number: u64) -> anyhow::Result<()> {
+        if let Some((from_height, events)) = self.inner.batch.lock().unwrap().as_mut()
+            && block_number >= *from_height
+        {
+            events.push(event);
+            return Ok(());
+        }
+        with_connection(&self.inner.conn, move |conn| {
+            let tx = conn.transaction()?;
+            insert(&tx, &event)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl EventHandler for SqliteIndexer {
+    async fn handle_mint(&self, event: &MintEvent) -> anyhow::Result<()> {
+        self.index(Indexed::Mint(event.clone()), event.meta.block_number)
+            .await
+    }
+
+    async fn handle_burn(&self, event: &BurnEvent) -> anyhow::Result<()> {
+        self.index(Indexed::Burn(event.clone()), event.meta.block_number)
+            .await
+    }
+
+    async fn on_batch_start(&self, from_height: u64, _to_height: u64) -> anyhow::Result<()> {
+        // Whatever a failed batch left behind is delivered again
+        *self.inner.batch.lock().unwrap() = Some((from_height, vec![]));
+        Ok(())
+    }
+
+    async fn on_batch_commit(&self, to_height: u64) -> anyhow::Result<()> {
+        let events = self
+            .inner
+            .batch
+            .lock()
+            .unwrap()
+            .take()
+            .map(|(_, events)| events)
+            .unwrap_or_default();
+        with_connection(&self.inner.conn, move |conn| {
+            let tx = conn.transaction()?;
+            for event in &events {
+                insert(&tx, event)?;
+            }
+            save_height(&tx, to_height)?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn on_reorg(&self, from_height: u64) -> anyhow::Result<()> {
+        with_connection(&self.inner.conn, move |conn| {
+            let tx = conn.transaction()?;
+            for table in ["mints", "burns", "blocks"] {
+                let column = if table == "blocks" {
+                    "number"
+                } else {
+                    "block_number"
+                };
+                tx.execute(
+                    &format!("DELETE FROM {table} WHERE {column} >= ?1"),
+                    params![from_height],
+                )?;
+            }
+            save_height(&tx, from_height.saturating_sub(1))?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl CheckpointStore for SqliteIndexer {
+    async fn load(&self) -> anyhow::Result<Option<Checkpoint>> {
+        with_connection(&self.inner.conn, |conn| {
+            checkpoint::load_checkpoint(conn, CHECKPOINT_NAME)
+        })
+        .await
+    }
+
+    async fn save(&self, checkpoint: Checkpoint) -> anyhow::Result<()> {
+        with_connection(&self.inner.conn, move |conn| {
+            checkpoint::save_checkpoint(conn, CHECKPOINT_NAME, checkpoint)
+        })
+        .await
+    }
+}
+
+// The monitor saves the block hash right after, through `CheckpointStore::save`
+fn save_height(tx: &Transaction, height: u64) -> anyhow::Result<()> {
+    checkpoint::save_checkpoint(tx, CHECKPOINT_NAME, Checkpoint::new(height, None))
+}
+
+// Events keep the id of their first delivery; a redelivered event is the same event
+fn insert(tx: &Transaction, event: &Indexed) -> anyhow::Result<()> {
+    let meta = match event {
+        Indexed::Mint(mint) => &mint.meta,
+        Indexed::Burn(burn) => &burn.meta,
+    };
+    tx.execute(
+        "INSERT INTO blocks (number, hash, timestamp) VALUES (?1, ?2, ?3)
+         ON CONFLICT(number) DO UPDATE SET hash = excluded.hash, timestamp = excluded.timestamp",
+        params![
+            meta.block_number,
+            format!("0x{:x}", meta.block_hash),
+            meta.block_timestamp
+        ],
+    )?;
+    match event {
+        Indexed::Mint(mint) => tx.execute(
+            "INSERT INTO mints (id, from_address, block_number, tx_hash, tx_index, event_index,
+                                recipient, value)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+             ON CONFLICT(id) DO NOTHING",
+            params![
+                meta.id(),
+                format!("0x{:x}", meta.from_address),
+                meta.block_number,
+                meta.tx_hash_hex(),
+                meta.tx_index,
+                meta.event_index,
+                format!("0x{:x}", Felt::from_hex(&mint.to)?),
+                mint.value.to_string(),
+            ],
+        )?,
+        Indexed::Burn(burn) => tx.execute(
+            "INSERT INTO burns (id, from_address, block_number, tx_hash, tx_index, event_index,
+                                sender, btc_addr, value, fee_rate, operator_id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT(id) DO NOTHING",
+            params![
+                meta.id(),
+                format!("0x{:x}", meta.from_address),
+                meta.block_number,
+                meta.tx_hash_hex(),
+                meta.tx_index,
+                meta.event_index,
+                burn.from,
+                burn.btc_addr,
+                burn.value.to_string(),
+                burn.fee_rate,
+                burn.operator_id,
+            ],
+        )?,
+    };
+    Ok(())
+}
+
+// The first seven columns of both queries are the event's metadata
+fn meta_from_row(row: &Row) -> anyhow::Result<EventMeta> {
+    Ok(EventMeta {
+        from_address: Felt::from_hex(&row.get::<_, String>(0)?)?,
+        block_hash: Felt::from_hex(&row.get::<_, String>(1)?)?,
+        block_number: row.get(2)?,
+        block_timestamp: row.get(3)?,
+        tx_hash: Felt::from_hex(&row.get::<_, String>(4)?)?,
+        tx_index: row.get(5)?,
+        event_index: row.get(6)?,
+    })
+}
+
+fn mint_from_row(row: &Row) -> anyhow::Result<MintEvent> {
+    Ok(MintEvent {
+        meta: meta_from_row(row)?,
+        to: row.get(7)?,
+        value: parse_amount(&row.get::<_, String>(8)?)?,
+    })
+}
+
+fn burn_from_row(row: &Row) -> anyhow::Result<BurnEvent> {
+    Ok(BurnEvent {
+        meta: meta_from_row(row)?,
+        from: row.get(7)?,
+        btc_addr: row.get(8)?,
+        value: parse_amount(&row.get::<_, String>(9)?)?,
+        fee_rate: row.get(10)?,
+        operator_id: row.get(11)?,
+    })
+}
+
+fn parse_amount(value: &str) -> anyhow::Result<BtcAmount> {
+    value
+        .parse()
+        .map(BtcAmount)
+        .map_err(|e| anyhow::anyhow!("Invalid amount {value}: {e}"))
+}
+
+/// A block's timestamp grows with its number, so tests can query time ranges.
+#[cfg(test)]
+fn indexed_meta(tx_hash: u64, block_number: u64) -> EventMeta {
+    EventMeta {
+        block_timestamp: 1748950000 + block_number,
+        tx_index: Some(0),
+        ..crate::test_utils::test_meta(tx_hash, block_number, 0)
+    }
+}
+
+#[cfg(test)]
+fn test_burn(tx_hash: u64, block_number: u64, operator_id: u32) -> BurnEvent {
+    crate::test_utils::test_burn(indexed_meta(tx_hash, block_number), operator_id)
+}
+
+#[tokio::test]
+async fn test_indexer_commits_batches_with_their_checkpoint() {
+    let indexer = SqliteIndexer::open_in_memory().unwrap();
+    let mint = MintEvent {
+        meta: indexed_meta(0x1, 10),
+        to: "0x72b1".to_string(),
+        value: BtcAmount::from_sats(u64::MAX),
+    };
+
+    indexer.on_batch_start(1, 20).await.unwrap();
+    indexer.handle_mint(&mint).await.unwrap();
+    indexer.handle_burn(&test_burn(0x2, 12, 1)).await.unwrap();
+    // Nothing is visible before the batch commits
+    assert!(indexer.mints_by_recipient("0x72b1").unwrap().is_empty());
+    assert_eq!(indexer.load().await.unwrap(), None);
+
+    // A failed batch is started again; what it delivered before is dropped
+    indexer.on_batch_start(1, 20).await.unwrap();
+    indexer.handle_mint(&mint).await.unwrap();
+    indexer.handle_mint(&mint).await.unwrap();
+    indexer.handle_burn(&test_burn(0x2, 12, 1)).await.unwrap();
+    indexer.handle_burn(&test_burn(0x3, 15, 2)).await.unwrap();
+    indexer.on_batch_commit(20).await.unwrap();
+    assert_eq!(
+        indexer.load().await.unwrap(),
+        Some(Checkpoint::new(20, None))
+    );
+
+    // Recipients match regardless of how the address is written
+    assert_eq!(indexer.mints_by_recipient("0x0072B1").unwrap(), vec![mint]);
+    let burns = indexer.burns_by_operator(1).unwrap();
+    assert_eq!(burns, vec![test_burn(0x2, 12, 1)]);
+
+    indexer
+        .mark_burn_fulfilled(&burns[0].meta.id(), "ab12")
+        .unwrap();
+    assert_eq!(
+        indexer.pending_burns().unwrap(),
+        vec![test_burn(0x3, 15, 2)]
+    );
+
+    assert_eq!(
+        indexer.totals(1748950010, 1748950013).unwrap(),
+        Totals {
+            mints: 1,
+            minted: BtcAmount::from_sats(u64::MAX),
+            burns: 1,
+            burned: BtcAmount::from_sats(200000),
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_indexer_rolls_back_reorganized_blocks() {
+    let indexer = SqliteIndexer::open_in_memory().unwrap();
+    indexer.on_batch_start(1, 20).await.unwrap();
+    indexer.handle_burn(&test_burn(0x2, 12, 1)).await.unwrap();
+    indexer.handle_burn(&test_burn(0x3, 15, 1)).await.unwrap();
+    indexer.on_batch_commit(20).await.unwrap();
+    indexer.mark_burn_fulfilled("0x3:0", "ab12").unwrap();
+
+    indexer.on_reorg(13).await.unwrap();
+    assert_eq!(
+        indexer.load().await.unwrap(),
+        Some(Checkpoint::new(12, None))
+    );
+    assert_eq!(indexer.burns_by_operator(1).unwrap().len(), 1);
+
+    // Replayed dead letters of earlier blocks are written even while a batch is open
+    indexer.on_batch_start(21, 30).await.unwrap();
+
+    // The replayed burn keeps its payout
+    indexer.handle_burn(&test_burn(0x3, 14, 1)).await.unwrap();
+    assert_eq!(
+        indexer.pending_burns().unwrap(),
+        vec![test_burn(0x2, 12, 1)]
+    );
+    assert_eq!(indexer.burns_by_operator(1).unwrap().len(), 2);
+}
@@ -1,6 +1,22 @@
+pub mod abi;
+pub mod bindings;
 pub mod bridge_client;
 pub mod chain;
+pub mod checkpoint;
+pub mod dead_letter;
+pub mod deploy;
 pub mod events;
+#[cfg(feature = "indexer")]
+pub mod indexer;
+pub mod lifecycle;
+pub mod provider;
 pub mod query_client;
+pub mod reconcile;
+pub mod retry;
+pub mod spec;
 pub mod types;
 pub mod utils;
+pub mod ws;
+
+#[cfg(test)]
+mod test_utils;
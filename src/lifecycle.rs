@@ -0,0 +1,937 @@
+//! Follows pegs from their submission through [`BitvmBridgeClient`] to the bridge events the
+//! [`EventMonitor`](crate::events::EventMonitor) sees. Register the [`PegTracker`] as the
+//! monitor's handler, or next to others in a `Vec<Box<dyn EventHandler>>`, and call
+//! [`PegTracker::refresh`] now and then for the statuses of submitted transactions.
+
+use crate::{
+    bridge_client::BitvmBridgeClient,
+    events::{BurnEvent, EventHandler, EventMeta, MintEvent},
+    types::{BtcAmount, PegContext},
+    utils::{btc_txid, replace_file},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use starknet::core::types::Felt;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+/// Where a peg is in its lifecycle. Peg-ins go `Submitted`, `Included`, `Minted`; peg-outs
+/// `BurnRequested`, `Burned`, `PaidOut`. Either ends in `Reverted` if its transaction fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PegState {
+    /// The mint transaction was sent
+    Submitted,
+    /// The mint transaction succeeded, its Mint event was not seen yet
+    Included,
+    /// The mint or burn transaction was reverted or rejected
+    Reverted,
+    Minted,
+    /// The burn transaction was sent
+    BurnRequested,
+    Burned,
+    /// The operator sent the BTC
+    PaidOut,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PegDirection {
+    In {
+        /// Starknet recipient
+        to: String,
+        /// Bitcoin transaction (as displayed, see [`btc_txid`]) and output holding the deposit
+        bitcoin_tx_hash: String,
+        output_index: u32,
+    },
+    Out {
+        btc_addr: String,
+        fee_rate: u32,
+        operator_id: u32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PegRecord {
+    /// `<tx hash>:<position in the mint call>` for peg-ins, the tx hash for peg-outs
+    pub id: String,
+    pub tx_hash: Felt,
+    pub amount: BtcAmount,
+    pub direction: PegDirection,
+    pub state: PegState,
+    /// The Mint or Burn event, once seen
+    pub event: Option<EventMeta>,
+    /// Bitcoin transaction that paid out a peg-out
+    pub payout_txid: Option<String>,
+}
+
+impl PegRecord {
+    pub fn is_peg_in(&self) -> bool {
+        matches!(self.direction, PegDirection::In { .. })
+    }
+}
+
+/// Durable storage for peg records.
+#[async_trait]
+pub trait PegStore: Send + Sync {
+    /// Stores the record, replacing an earlier one with the same id.
+    async fn put(&self, record: PegRecord) -> anyhow::Result<()>;
+
+    async fn get(&self, id: &str) -> anyhow::Result<Option<PegRecord>>;
+
+    /// The records of a mint or burn transaction, in the order first stored.
+    async fn by_tx_hash(&self, tx_hash: Felt) -> anyhow::Result<Vec<PegRecord>>;
+
+    /// The peg-ins of a Bitcoin output (its transaction as displayed, see [`btc_txid`]), in the
+    /// order first stored.
+    async fn by_outpoint(
+        &self,
+        bitcoin_tx_hash: &str,
+        output_index: u32,
+    ) -> anyhow::Result<Vec<PegRecord>>;
+
+    /// The records whose event is in block `height` or above, in the order first stored.
+    async fn with_events_from(&self, height: u64) -> anyhow::Result<Vec<PegRecord>>;
+
+    /// Every record, in the order first stored.
+    async fn all(&self) -> anyhow::Result<Vec<PegRecord>>;
+}
+
+/// Keeps peg records in a JSON lines journal, read once and then served from memory. Every
+/// `put` appends a line, the last line of a record wins, and the file is compacted once most of
+/// its lines are outdated.
+pub struct FilePegStore {
+    path: PathBuf,
+    // Loaded on first use; holding the lock also serializes writes to the file
+    records: Mutex<Option<FileRecords>>,
+}
+
+#[derive(Default)]
+struct FileRecords {
+    /// In the order first stored
+    records: Vec<PegRecord>,
+    /// Position of each record in `records`
+    by_id: HashMap<String, usize>,
+    /// Positions in `records`, ascending
+    by_tx_hash: HashMap<Felt, Vec<usize>>,
+    by_outpoint: HashMap<(String, u32), Vec<usize>>,
+    by_event_height: BTreeMap<u64, Vec<usize>>,
+    /// Lines in the journal
+    lines: usize,
+    /// The last line was cut short by a crash, so the next write rewrites the file
+    torn: bool,
+}
+
+impl FileRecords {
+    /// Journals with at least this many lines are compacted once half of them are outdated.
+    const COMPACT_AFTER: usize = 1000;
+
+    fn insert(&mut self, record: PegRecord) {
+        let position = match self.by_id.get(&record.id) {
+            Some(&i) => {
+                self.unindex(i);
+                self.records[i] = record;
+                i
+            }
+            None => {
+                self.by_id.insert(record.id.clone(), self.records.len());
+                self.records.push(record);
+                self.records.len() - 1
+            }
+        };
+        self.index(position);
+    }
+
+    fn index(&mut self, i: usize) {
+        let add = |positions: &mut Vec<usize>| {
+            if let Err(at) = positions.binary_search(&i) {
+                positions.insert(at, i);
+            }
+        };
+        let record = &self.records[i];
+        add(self.by_tx_hash.entry(record.tx_hash).or_default());
+        if let Some(outpoint) = outpoint(record) {
+            add(self.by_outpoint.entry(outpoint).or_default());
+        }
+        if let Some(event) = &record.event {
+            add(self.by_event_height.entry(event.block_number).or_default());
+        }
+    }
+
+    fn unindex(&mut self, i: usize) {
+        let record = &self.records[i];
+        if let Some(positions) = self.by_tx_hash.get_mut(&record.tx_hash) {
+            positions.retain(|&p| p != i);
+            if positions.is_empty() {
+                self.by_tx_hash.remove(&record.tx_hash);
+            }
+        }
+        if let Some(outpoint) = outpoint(record)
+            && let Some(positions) = self.by_outpoint.get_mut(&outpoint)
+        {
+            positions.retain(|&p| p != i);
+            if positions.is_empty() {
+                self.by_outpoint.remove(&outpoint);
+            }
+        }
+        if let Some(event) = &record.event
+            && let Some(positions) = self.by_event_height.get_mut(&event.block_number)
+        {
+            positions.retain(|&p| p != i);
+            if positions.is_empty() {
+                self.by_event_height.remove(&event.block_number);
+            }
+        }
+    }
+
+    fn at(&self, positions: impl IntoIterator<Item = usize>) -> Vec<PegRecord> {
+        positions
+            .into_iter()
+            .map(|i| self.records[i].clone())
+            .collect()
+    }
+
+    fn needs_compaction(&self) -> bool {
+        self.torn || (self.lines >= Self::COMPACT_AFTER && self.lines >= 2 * self.records.len())
+    }
+}
+
+fn outpoint(record: &PegRecord) -> Option<(String, u32)> {
+    match &record.direction {
+        PegDirection::In {
+            bitcoin_tx_hash,
+            output_index,
+            ..
+        } => Some((bitcoin_tx_hash.clone(), *output_index)),
+        PegDirection::Out { .. } => None,
+    }
+}
+
+impl FilePegStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            records: Mutex::new(None),
+        }
+    }
+
+    async fn records(&self) -> anyhow::Result<MappedMutexGuard<'_, FileRecords>> {
+        let mut records = self.records.lock().await;
+        if records.is_none() {
+            let path = self.path.clone();
+            *records = Some(tokio::task::spawn_blocking(move || read_records(&path)).await??);
+        }
+        Ok(MutexGuard::map(records, |records| {
+            records.as_mut().unwrap()
+        }))
+    }
+}
+
+fn read_records(path: &Path) -> anyhow::Result<FileRecords> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(FileRecords::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut records = FileRecords::default();
+    let mut lines = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .peekable();
+    while let Some(line) = lines.next() {
+        let record: PegRecord = match serde_json::from_str(line) {
+            Ok(record) => record,
+            // A crash while appending leaves the last line without its newline
+            Err(_) if lines.peek().is_none() && !content.ends_with('\n') => {
+                records.torn = true;
+                break;
+            }
+            Err(e) => anyhow::bail!("Invalid peg record in {}: {e}", path.display()),
+        };
+        records.insert(record);
+        records.lines += 1;
+    }
+    Ok(records)
+}
+
+fn append_line(path: &Path, line: &str) -> std::io::Result<()> {
+    let mut file = fs::OpenOptions::new().append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    file.sync_data()
+}
+
+#[async_trait]
+impl PegStore for FilePegStore {
+    async fn put(&self, record: PegRecord) -> anyhow::Result<()> {
+        let mut records = self.records().await?;
+        let path = self.path.clone();
+
+        // Memory is only updated once the file was written
+        if records.lines == 0 || records.needs_compaction() {
+            let position = records.by_id.get(&record.id).copied();
+            let stored = records.records.iter().enumerate().map(|(i, stored)| {
+                if Some(i) == position { &record } else { stored }
+            });
+            let mut content = String::new();
+            for stored in stored.chain(position.is_none().then_some(&record)) {
+                content += &serde_json::to_string(stored)?;
+                content.push('\n');
+            }
+            tokio::task::spawn_blocking(move || replace_file(&path, content.as_bytes())).await??;
+            records.lines = records.records.len() + usize::from(position.is_none());
+            records.torn = false;
+        } else {
+            let line = serde_json::to_string(&record)? + "\n";
+            tokio::task::spawn_blocking(move || append_line(&path, &line)).await??;
+            records.lines += 1;
+        }
+        records.insert(record);
+        Ok(())
+    }
+
+    async fn get(&self, id: &str) -> anyhow::Result<Option<PegRecord>> {
+        let records = self.records().await?;
+        Ok(records.by_id.get(id).map(|&i| records.records[i].clone()))
+    }
+
+    async fn by_tx_hash(&self, tx_hash: Felt) -> anyhow::Result<Vec<PegRecord>> {
+        let records = self.records().await?;
+        let positions = records
+            .by_tx_hash
+            .get(&tx_hash)
+            .cloned()
+            .unwrap_or_default();
+        Ok(records.at(positions))
+    }
+
+    async fn by_outpoint(
+        &self,
+        bitcoin_tx_hash: &str,
+        output_index: u32,
+    ) -> anyhow::Result<Vec<PegRecord>> {
+        let records = self.records().await?;
+        let positions = records
+            .by_outpoint
+            .get(&(bitcoin_tx_hash.to_string(), output_index))
+            .cloned()
+            .unwrap_or_default();
+        Ok(records.at(positions))
+    }
+
+    async fn with_events_from(&self, height: u64) -> anyhow::Result<Vec<PegRecord>> {
+        let records = self.records().await?;
+        let mut positions: Vec<usize> = records
+            .by_event_height
+            .range(height..)
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect();
+        positions.sort_unstable();
+        Ok(records.at(positions))
+    }
+
+    async fn all(&self) -> anyhow::Result<Vec<PegRecord>> {
+        Ok(self.records().await?.records.clone())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqlitePegStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{PegRecord, PegStore, outpoint};
+    use crate::utils::with_connection;
+    use async_trait::async_trait;
+    use rusqlite::{Connection, OptionalExtension, Params, params};
+    use starknet::core::types::Felt;
+    use std::{
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    pub struct SqlitePegStore {
+        conn: Arc<Mutex<Connection>>,
+    }
+
+    impl SqlitePegStore {
+        pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+            Self::from_connection(Connection::open(path)?)
+        }
+
+        pub fn open_in_memory() -> anyhow::Result<Self> {
+            Self::from_connection(Connection::open_in_memory()?)
+        }
+
+        pub fn from_connection(conn: Connection) -> anyhow::Result<Self> {
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS pegs (
+                    seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                    id TEXT NOT NULL UNIQUE,
+                    tx_hash TEXT NOT NULL,
+                    bitcoin_tx_hash TEXT,
+                    output_index INTEGER,
+                    event_block INTEGER,
+                    record TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS pegs_by_tx_hash ON pegs (tx_hash);
+                CREATE INDEX IF NOT EXISTS pegs_by_outpoint ON pegs (bitcoin_tx_hash, output_index);
+                CREATE INDEX IF NOT EXISTS pegs_by_event_block ON pegs (event_block);",
+            )?;
+            Ok(Self {
+                conn: Arc::new(Mutex::new(conn)),
+            })
+        }
+
+        async fn select(
+            &self,
+            condition: &'static str,
+            params: impl Params + Send + 'static,
+        ) -> anyhow::Result<Vec<PegRecord>> {
+            with_connection(&self.conn, move |conn| {
+                let mut stmt = conn.prepare(&format!(
+                    "SELECT record FROM pegs WHERE {condition} ORDER BY seq"
+                ))?;
+                let rows = stmt.query_map(params, |row| row.get::<_, String>(0))?;
+                rows.map(|record| Ok(serde_json::from_str(&record?)?))
+                    .collect()
+            })
+            .await
+        }
+    }
+
+    #[async_trait]
+    impl PegStore for SqlitePegStore {
+        async fn put(&self, record: PegRecord) -> anyhow::Result<()> {
+            let (bitcoin_tx_hash, output_index) = outpoint(&record).unzip();
+            let event_block = record.event.as_ref().map(|e| e.block_number);
+            let tx_hash = format!("0x{:x}", record.tx_hash);
+            let json = serde_json::to_string(&record)?;
+            with_connection(&self.conn, move |conn| {
+                conn.execute(
+                    "INSERT INTO pegs (id, tx_hash, bitcoin_tx_hash, output_index, event_block, record)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                     ON CONFLICT(id) DO UPDATE SET
+                        tx_hash = excluded.tx_hash,
+                        bitcoin_tx_hash = excluded.bitcoin_tx_hash,
+                        output_index = excluded.output_index,
+                        event_block = excluded.event_block,
+                        record = excluded.record",
+                    params![
+                        record.id,
+                        tx_hash,
+                        bitcoin_tx_hash,
+                        output_index,
+                        event_block,
+                        json
+                    ],
+                )?;
+                Ok(())
+            })
+            .await
+        }
+
+        async fn get(&self, id: &str) -> anyhow::Result<Option<PegRecord>> {
+            let id = id.to_string();
+            with_connection(&self.conn, move |conn| {
+                let record: Option<String> = conn
+                    .query_row("SELECT record FROM pegs WHERE id = ?1", [id], |row| {
+                        row.get(0)
+                    })
+                    .optional()?;
+                Ok(record.map(|r| serde_json::from_str(&r)).transpose()?)
+            })
+            .await
+        }
+
+        async fn by_tx_hash(&self, tx_hash: Felt) -> anyhow::Result<Vec<PegRecord>> {
+            self.select("tx_hash = ?1", [format!("0x{tx_hash:x}")])
+                .await
+        }
+
+        async fn by_outpoint(
+            &self,
+            bitcoin_tx_hash: &str,
+            output_index: u32,
+        ) -> anyhow::Result<Vec<PegRecord>> {
+            self.select(
+                "bitcoin_tx_hash = ?1 AND output_index = ?2",
+                (bitcoin_tx_hash.to_string(), output_index),
+            )
+            .await
+        }
+
+        async fn with_events_from(&self, height: u64) -> anyhow::Result<Vec<PegRecord>> {
+            self.select("event_block >= ?1", [height]).await
+        }
+
+        async fn all(&self) -> anyhow::Result<Vec<PegRecord>> {
+            self.select("1", []).await
+        }
+    }
+}
+
+/// Records pegs submitted through [`BitvmBridgeClient`] and moves them along as their
+/// transactions and events come in. Clones share the store.
+#[derive(Clone)]
+pub struct PegTracker {
+    store: Arc<dyn PegStore>,
+    // Serializes the read-modify-write of updates from the monitor and `refresh`
+    lock: Arc<Mutex<()>>,
+}
+
+impl PegTracker {
+    pub fn new(store: Arc<dyn PegStore>) -> Self {
+        Self {
+            store,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Mints through `client` and tracks every peg of the call.
+    pub async fn mint(
+        &self,
+        client: &BitvmBridgeClient,
+        contexts: &[PegContext],
+    ) -> anyhow::Result<String> {
+        let tx_hash = client.mint_tokens(contexts).await?;
+        self.record_mint(&tx_hash, contexts).await?;
+        Ok(tx_hash)
+    }
+
+    /// Burns through `client` and tracks the peg-out.
+    pub async fn burn(
+        &self,
+        client: &BitvmBridgeClient,
+        btc_address: &str,
+        fee_rate: u32,
+        amount: u64,
+        operator_id: u32,
+    ) -> anyhow::Result<String> {
+        let tx_hash = client
+            .burn_tokens(btc_address, fee_rate, amount, operator_id)
+            .await?;
+        self.record_burn(&tx_hash, btc_address, fee_rate, amount, operator_id)
+            .await?;
+        Ok(tx_hash)
+    }
+
+    /// Tracks the pegs of a mint transaction sent some other way.
+    pub async fn record_mint(&self, tx_hash: &str, contexts: &[PegContext]) -> anyhow::Result<()> {
+        let tx_hash = Felt::from_hex(tx_hash)?;
+        let _guard = self.lock.lock().await;
+        for (position, ctx) in contexts.iter().enumerate() {
+            self.store
+                .put(PegRecord {
+                    id: format!("0x{tx_hash:x}:{position}"),
+                    tx_hash,
+                    amount: ctx.amount.into(),
+                    direction: PegDirection::In {
+                        to: format!("0x{:x}", Felt::from_hex(&ctx.to)?),
+                        bitcoin_tx_hash: btc_txid(&ctx.bitcoin_tx_hash),
+                        output_index: ctx.output_index,
+                    },
+                    state: PegState::Submitted,
+                    event: None,
+                    payout_txid: None,
+                })
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Tracks a burn transaction sent some other way.
+    pub async fn record_burn(
+        &self,
+        tx_hash: &str,
+        btc_address: &str,
+        fee_rate: u32,
+        amount: u64,
+        operator_id: u32,
+    ) -> anyhow::Result<()> {
+        let tx_hash = Felt::from_hex(tx_hash)?;
+        let _guard = self.lock.lock().await;
+        self.store
+            .put(PegRecord {
+                id: format!("0x{tx_hash:x}"),
+                tx_hash,
+                amount: amount.into(),
+                direction: PegDirection::Out {
+                    btc_addr: btc_address.to_string(),
+                    fee_rate,
+                    operator_id,
+                },
+                state: PegState::BurnRequested,
+                event: None,
+                payout_txid: None,
+            })
+            .await
+    }
+
+    /// Checks the transactions still waiting for their event: reverted or rejected ones end
+    /// their pegs, included peg-ins move on to `Included`.
+    pub async fn refresh(&self, client: &BitvmBridgeClient) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let waiting: Vec<PegRecord> = self
+            .store
+            .all()
+            .await?
+            .into_iter()
+            .filter(|r| {
+                matches!(
+                    r.state,
+                    PegState::Submitted | PegState::Included | PegState::BurnRequested
+                )
+            })
+            .collect();
+        let tx_hashes: Vec<String> = waiting
+            .iter()
+            .map(|r| format!("0x{:x}", r.tx_hash))
+            .collect();
+        let tx_hashes: Vec<&str> = tx_hashes.iter().map(String::as_str).collect();
+        let statuses = client.get_transaction_statuses(&tx_hashes).await?;
+
+        for (mut record, status) in waiting.into_iter().zip(statuses) {
+            // Unknown transactions may not have reached the node yet
+            let Ok(status) = status else {
+                continue;
+            };
+            let state = if status.is_rejected() || status.is_reverted() {
+                PegState::Reverted
+            } else if record.state == PegState::Submitted && status.is_succeeded() {
+                PegState::Included
+            } else {
+                continue;
+            };
+            record.state = state;
+            self.store.put(record).await?;
+        }
+        Ok(())
+    }
+
+    /// Records the Bitcoin transaction that paid out the peg-out of `burn_tx_hash`.
+    pub async fn mark_paid_out(&self, burn_tx_hash: &str, btc_txid: &str) -> anyhow::Result<()> {
+        let id = format!("0x{:x}", Felt::from_hex(burn_tx_hash)?);
+        let _guard = self.lock.lock().await;
+        let mut record = self
+            .get(&id)
+            .await?
+            .filter(|r| !r.is_peg_in())
+            .ok_or_else(|| anyhow::anyhow!("Unknown peg-out {id}"))?;
+        if record.state != PegState::Burned {
+            anyhow::bail!("Peg-out {id} is {:?}, not burned", record.state);
+        }
+        record.state = PegState::PaidOut;
+        record.payout_txid = Some(btc_txid.to_string());
+        self.store.put(record).await
+    }
+
+    pub async fn get(&self, id: &str) -> anyhow::Result<Option<PegRecord>> {
+        self.store.get(id).await
+    }
+
+    /// The pegs of a mint or burn transaction.
+    pub async fn by_tx_hash(&self, tx_hash: &str) -> anyhow::Result<Vec<PegRecord>> {
+        self.store.by_tx_hash(Felt::from_hex(tx_hash)?).await
+    }
+
+    /// The peg-ins of a Bitcoin output; `bitcoin_tx_hash` is in internal byte order, as in
+    /// [`PegContext`].
+    pub async fn by_outpoint(
+        &self,
+        bitcoin_tx_hash: &[u8; 32],
+        output_index: u32,
+    ) -> anyhow::Result<Vec<PegRecord>> {
+        self.store
+            .by_outpoint(&btc_txid(bitcoin_tx_hash), output_index)
+            .await
+    }
+
+    pub async fn by_state(&self, state: PegState) -> anyhow::Result<Vec<PegRecord>> {
+        Ok(self
+            .store
+            .all()
+            .await?
+            .into_iter()
+            .filter(|r| r.state == state)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl EventHandler for PegTracker {
+    async fn handle_mint(&self, event: &MintEvent) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        // The first peg of the transaction still waiting for a Mint of this amount to this
+        // recipient; pegs submitted by others are not tracked
+        let record = self
+            .store
+            .by_tx_hash(event.meta.tx_hash)
+            .await?
+            .into_iter()
+            .find(|r| {
+                r.amount == event.value
+                    && matches!(r.state, PegState::Submitted | PegState::Included)
+                    && matches!(&r.direction, PegDirection::In { to, .. } if *to == event.to)
+            });
+        if let Some(mut record) = record {
+            record.state = PegState::Minted;
+            record.event = Some(event.meta.clone());
+            self.store.put(record).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_burn(&self, event: &BurnEvent) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        let id = format!("0x{:x}", event.meta.tx_hash);
+        if let Some(mut record) = self.get(&id).await?
+            && record.state == PegState::BurnRequested
+        {
+            record.state = PegState::Burned;
+            record.event = Some(event.meta.clone());
+            self.store.put(record).await?;
+        }
+        Ok(())
+    }
+
+    async fn on_reorg(&self, from_height: u64) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().await;
+        for mut record in self.store.with_events_from(from_height).await? {
+            // The events are delivered again if their transactions are still in the chain
+            let state = match record.state {
+                PegState::Minted => PegState::Included,
+                PegState::Burned => PegState::BurnRequested,
+                _ => continue,
+            };
+            record.state = state;
+            record.event = None;
+            self.store.put(record).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use crate::test_utils::{test_burn, test_context, test_meta};
+
+#[tokio::test]
+async fn test_tracker_follows_pegs_through_their_lifecycle() {
+    use crate::{chain::StarknetChainId, test_utils::MockRpc};
+    use serde_json::json;
+
+    let rpc = MockRpc::start(|method, params| match method {
+        "starknet_specVersion" => Ok(json!("0.8.1")),
+        "starknet_getTransactionStatus" => match params["transaction_hash"].as_str() {
+            Some("0x1") => Ok(json!({
+                "finality_status": "ACCEPTED_ON_L2",
+                "execution_status": "SUCCEEDED"
+            })),
+            Some("0x2") => Ok(json!({"finality_status": "RECEIVED"})),
+            Some("0x3") => Ok(json!({
+                "finality_status": "ACCEPTED_ON_L2",
+                "execution_status": "REVERTED"
+            })),
+            _ => Err(json!({"code": 29, "message": "Transaction hash not found"})),
+        },
+        _ => Err(json!({"code": -32601, "message": "Method not found"})),
+    })
+    .await;
+    let client = BitvmBridgeClient::new(
+        &rpc.url,
+        "0x37f",
+        "0x38f",
+        "0x293a3005233337f890c576e5c2768a47595f4cdbabd006c9898ce38a961fe7a",
+        "0x72b1",
+        &StarknetChainId::Sepolia,
+    );
+
+    let path = std::env::temp_dir().join(format!("pegs-{}", rand::random::<u64>()));
+    let tracker = PegTracker::new(Arc::new(FilePegStore::new(&path)));
+    let contexts = [test_context("0x00aa", 500000), test_context("0xbb", 700000)];
+    tracker.record_mint("0x1", &contexts).await.unwrap();
+    tracker
+        .record_burn("0x2", "bcrt1qexample", 5, 200000, 1)
+        .await
+        .unwrap();
+    tracker
+        .record_burn("0x3", "bcrt1qexample", 5, 300000, 2)
+        .await
+        .unwrap();
+    tracker.record_mint("0x4", &contexts[..1]).await.unwrap();
+
+    tracker.refresh(&client).await.unwrap();
+    let states = |records: Vec<PegRecord>| records.iter().map(|r| r.state).collect::<Vec<_>>();
+    assert_eq!(
+        states(tracker.by_tx_hash("0x1").await.unwrap()),
+        vec![PegState::Included, PegState::Included]
+    );
+    assert_eq!(
+        tracker.get("0x2").await.unwrap().unwrap().state,
+        PegState::BurnRequested
+    );
+    assert_eq!(
+        tracker.get("0x3").await.unwrap().unwrap().state,
+        PegState::Reverted
+    );
+    // Not known to the node yet
+    assert_eq!(
+        tracker.get("0x4:0").await.unwrap().unwrap().state,
+        PegState::Submitted
+    );
+
+    // Mints are matched by transaction, recipient and amount
+    tracker
+        .handle_mint(&MintEvent {
+            meta: test_meta(0x1, 890870, 0),
+            to: "0xbb".to_string(),
+            value: BtcAmount::from_sats(700000),
+        })
+        .await
+        .unwrap();
+    tracker
+        .handle_burn(&test_burn(test_meta(0x2, 890871, 0), 1))
+        .await
+        .unwrap();
+    assert_eq!(
+        states(tracker.by_tx_hash("0x1").await.unwrap()),
+        vec![PegState::Included, PegState::Minted]
+    );
+    tracker.mark_paid_out("0x2", "cd34").await.unwrap();
+    assert!(tracker.mark_paid_out("0x1", "cd34").await.is_err());
+
+    // A reorg undoes the mint, but not the payout; the tracker's state survives a restart
+    tracker.on_reorg(890870).await.unwrap();
+    let tracker = PegTracker::new(Arc::new(FilePegStore::new(&path)));
+    assert_eq!(tracker.by_state(PegState::Minted).await.unwrap(), vec![]);
+    let paid_out = tracker.by_state(PegState::PaidOut).await.unwrap();
+    assert_eq!(paid_out.len(), 1);
+    assert_eq!(paid_out[0].payout_txid.as_deref(), Some("cd34"));
+    assert_eq!(paid_out[0].event, Some(test_meta(0x2, 890871, 0)));
+    fs::remove_file(path).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_peg_store_replaces_records() {
+    let tracker = PegTracker::new(Arc::new(SqlitePegStore::open_in_memory().unwrap()));
+    tracker
+        .record_burn("0x2", "bcrt1qexample", 5, 200000, 1)
+        .await
+        .unwrap();
+    tracker
+        .record_mint("0x1", &[test_context("0xaa", 1)])
+        .await
+        .unwrap();
+    // Not burned yet
+    assert!(tracker.mark_paid_out("0x02", "cd34").await.is_err());
+    tracker
+        .handle_burn(&test_burn(test_meta(0x2, 890871, 0), 1))
+        .await
+        .unwrap();
+    tracker.mark_paid_out("0x02", "cd34").await.unwrap();
+
+    let records = tracker.store.all().await.unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].state, PegState::PaidOut);
+    assert_eq!(records[1].id, "0x1:0");
+}
+
+#[tokio::test]
+async fn test_peg_stores_look_up_records_by_key() {
+    let path = std::env::temp_dir().join(format!("pegs-{}", rand::random::<u64>()));
+    let stores: Vec<Arc<dyn PegStore>> = vec![
+        Arc::new(FilePegStore::new(&path)),
+        #[cfg(feature = "sqlite")]
+        Arc::new(SqlitePegStore::open_in_memory().unwrap()),
+    ];
+    for store in stores {
+        let tracker = PegTracker::new(store.clone());
+        let contexts = [test_context("0xaa", 500000), test_context("0xbb", 700000)];
+        tracker.record_mint("0x1", &contexts).await.unwrap();
+        tracker
+            .record_burn("0x2", "bcrt1qexample", 5, 200000, 1)
+            .await
+            .unwrap();
+        tracker
+            .handle_burn(&test_burn(test_meta(0x2, 890871, 0), 1))
+            .await
+            .unwrap();
+
+        let ids = |records: Vec<PegRecord>| records.into_iter().map(|r| r.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(store.by_tx_hash(Felt::ONE).await.unwrap()),
+            vec!["0x1:0", "0x1:1"]
+        );
+        // Both contexts spend output 0 of the same Bitcoin transaction
+        assert_eq!(
+            ids(tracker.by_outpoint(&[0xab; 32], 0).await.unwrap()),
+            vec!["0x1:0", "0x1:1"]
+        );
+        assert!(
+            tracker
+                .by_outpoint(&[0xab; 32], 1)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(
+            ids(store.with_events_from(890871).await.unwrap()),
+            vec!["0x2"]
+        );
+        assert!(store.with_events_from(890872).await.unwrap().is_empty());
+
+        // A replaced record is found under its new keys only
+        tracker.on_reorg(890871).await.unwrap();
+        assert!(store.with_events_from(0).await.unwrap().is_empty());
+    }
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn test_file_peg_store_appends_to_its_journal() {
+    let path = std::env::temp_dir().join(format!("pegs-{}", rand::random::<u64>()));
+    let tracker = PegTracker::new(Arc::new(FilePegStore::new(&path)));
+    tracker
+        .record_mint("0x1", &[test_context("0xaa", 500000)])
+        .await
+        .unwrap();
+    tracker
+        .record_burn("0x2", "bcrt1qexample", 5, 200000, 1)
+        .await
+        .unwrap();
+    tracker
+        .record_burn("0x2", "bcrt1qexample", 6, 200000, 1)
+        .await
+        .unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+
+    // The last line of a record wins, and a line cut short by a crash is dropped
+    fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap()
+        .write_all(b"{\"id\":\"0x3\"")
+        .unwrap();
+    let store = FilePegStore::new(&path);
+    let records = store.all().await.unwrap();
+    assert_eq!(records.len(), 2);
+    assert!(matches!(
+        records[1].direction,
+        PegDirection::Out { fee_rate: 6, .. }
+    ));
+
+    // The next write compacts the file
+    let mut record = records[0].clone();
+    record.state = PegState::Included;
+    store.put(record).await.unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    assert_eq!(
+        FilePegStore::new(&path).all().await.unwrap()[0].state,
+        PegState::Included
+    );
+    fs::remove_file(path).unwrap();
+}
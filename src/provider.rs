@@ -0,0 +1,690 @@
+use crate::retry::{RateLimiter, RetryPolicy, RpcMetrics, RpcMetricsSnapshot};
+use async_trait::async_trait;
+use serde::{Serialize, de::DeserializeOwned};
+use starknet::providers::{
+    ProviderRequestData, Url,
+    jsonrpc::{
+        HttpTransport, HttpTransportError, JsonRpcMethod, JsonRpcResponse, JsonRpcTransport,
+    },
+};
+use std::{
+    fmt,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct FailoverConfig {
+    /// Endpoints further behind the highest known block than this are considered lagging and
+    /// never receive transactions
+    pub max_block_lag: u64,
+    /// How stale the health information may get before a request refreshes it in the background
+    pub health_check_interval: Duration,
+    /// How long a health probe may take before the endpoint counts as failed
+    pub health_check_timeout: Duration,
+    /// Consecutive failures after which an endpoint is marked unhealthy
+    pub failure_threshold: u32,
+    pub retry: RetryPolicy,
+    /// Shared by every client using this transport, and by other transports holding a clone
+    pub rate_limiter: Option<RateLimiter>,
+}
+
+impl Default for FailoverConfig {
+    fn default() -> Self {
+        Self {
+            max_block_lag: 3,
+            health_check_interval: Duration::from_secs(30),
+            health_check_timeout: Duration::from_secs(5),
+            failure_threshold: 3,
+            retry: RetryPolicy::default(),
+            rate_limiter: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointHealth {
+    pub url: Url,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    pub latest_block: Option<u64>,
+    /// Blocks behind the most advanced endpoint, known when the last health check reached it
+    pub block_lag: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum FailoverTransportError {
+    /// No healthy endpoint is close enough to the chain head to accept a transaction
+    NoSyncedEndpoint,
+    /// Every candidate endpoint failed, carrying the last failure
+    Transport {
+        url: Url,
+        source: HttpTransportError,
+    },
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for FailoverTransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSyncedEndpoint => write!(f, "No synced RPC endpoint available"),
+            Self::Transport { url, source } => write!(f, "RPC endpoint {url} failed: {source}"),
+            Self::Json(e) => write!(f, "Invalid JSON-RPC payload: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FailoverTransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::NoSyncedEndpoint => None,
+            Self::Transport { source, .. } => Some(source),
+            Self::Json(e) => Some(e),
+        }
+    }
+}
+
+/// A [`JsonRpcTransport`] spreading requests over several RPC endpoints.
+///
+/// Reads go to the healthiest endpoint first and fail over to the next one on transport errors.
+/// Transactions are only ever submitted to endpoints that are healthy and within
+/// [`FailoverConfig::max_block_lag`] of the chain head, so a signed transaction never lands on a
+/// node that might reject it against stale state. They are retried only while no node has
+/// received them, since one whose response was lost may already have been accepted.
+#[derive(Clone)]
+pub struct FailoverTransport {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    endpoints: Vec<Endpoint>,
+    config: FailoverConfig,
+    last_health_check: tokio::sync::Mutex<Option<Instant>>,
+    refreshing_health: AtomicBool,
+    metrics: RpcMetrics,
+}
+
+struct Endpoint {
+    transport: HttpTransport,
+    health: Mutex<EndpointHealth>,
+}
+
+impl FailoverTransport {
+    pub fn new(urls: &[&str]) -> Self {
+        Self::with_config(urls, FailoverConfig::default())
+    }
+
+    pub fn with_config(urls: &[&str], config: FailoverConfig) -> Self {
+        assert!(!urls.is_empty(), "At least one RPC endpoint is required");
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let url = Url::parse(url).expect("Invalid URL");
+                Endpoint {
+                    transport: HttpTransport::new(url.clone()),
+                    health: Mutex::new(EndpointHealth {
+                        url,
+                        healthy: true,
+                        consecutive_failures: 0,
+                        latest_block: None,
+                        block_lag: None,
+                        last_error: None,
+                    }),
+                }
+            })
+            .collect();
+        Self {
+            inner: Arc::new(Inner {
+                endpoints,
+                config,
+                last_health_check: tokio::sync::Mutex::new(None),
+                refreshing_health: AtomicBool::new(false),
+                metrics: RpcMetrics::default(),
+            }),
+        }
+    }
+
+    pub fn metrics(&self) -> RpcMetricsSnapshot {
+        self.inner.metrics.snapshot()
+    }
+
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.inner
+            .endpoints
+            .iter()
+            .map(|e| e.health.lock().unwrap().clone())
+            .collect()
+    }
+
+    /// Probes every endpoint for its latest block and recomputes block lags.
+    pub async fn check_health(&self) {
+        self.probe_endpoints().await;
+        *self.inner.last_health_check.lock().await = Some(Instant::now());
+    }
+
+    async fn probe_endpoints(&self) {
+        let timeout = self.inner.config.health_check_timeout;
+        let probes = self.inner.endpoints.iter().map(|endpoint| async move {
+            if let Some(limiter) = &self.inner.config.rate_limiter {
+                let waited = limiter.acquire(1).await;
+                self.inner.metrics.record_throttle(waited);
+            }
+            let result = tokio::time::timeout(
+                timeout,
+                endpoint
+                    .transport
+                    .send_request::<_, u64>(JsonRpcMethod::BlockNumber, serde_json::json!([])),
+            )
+            .await;
+            match result {
+                Ok(Ok(JsonRpcResponse::Success { result, .. })) => {
+                    endpoint.health.lock().unwrap().latest_block = Some(result);
+                    self.record_success(endpoint);
+                    Some(result)
+                }
+                Ok(Ok(JsonRpcResponse::Error { error, .. })) => {
+                    self.record_failure(endpoint, error.message);
+                    None
+                }
+                Ok(Err(e)) => {
+                    self.record_failure(endpoint, e.to_string());
+                    None
+                }
+                Err(_) => {
+                    self.record_failure(endpoint, format!("No block number within {timeout:?}"));
+                    None
+                }
+            }
+        });
+        let latest = futures::future::join_all(probes).await;
+
+        // Taken from this round's answers only, so an endpoint that once reported a wrong head
+        // does not leave the others looking lagged
+        let head = latest.iter().flatten().max().copied();
+        for (endpoint, latest) in self.inner.endpoints.iter().zip(latest) {
+            let mut health = endpoint.health.lock().unwrap();
+            health.block_lag = match (latest, head, health.healthy) {
+                (Some(latest), Some(head), true) => Some(head - latest),
+                _ => None,
+            };
+        }
+    }
+
+    /// The first request waits for a health check, concurrent ones for the same check. Later
+    /// checks run in the background, one at a time, while requests use the last known health.
+    async fn refresh_health_if_stale(&self) {
+        let mut last = self.inner.last_health_check.lock().await;
+        match *last {
+            None => {
+                self.probe_endpoints().await;
+                *last = Some(Instant::now());
+            }
+            Some(checked) if checked.elapsed() >= self.inner.config.health_check_interval => {
+                if !self.inner.refreshing_health.swap(true, Ordering::SeqCst) {
+                    let transport = self.clone();
+                    tokio::spawn(async move {
+                        transport.check_health().await;
+                        transport
+                            .inner
+                            .refreshing_health
+                            .store(false, Ordering::SeqCst);
+                    });
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// Endpoints in the order they should be tried. Reads may use every endpoint, unhealthy ones
+    /// last; writes are restricted to healthy endpoints that are not lagging.
+    async fn candidates(&self, write: bool) -> Result<Vec<&Endpoint>, FailoverTransportError> {
+        self.refresh_health_if_stale().await;
+
+        let mut candidates: Vec<(&Endpoint, EndpointHealth)> = self
+            .inner
+            .endpoints
+            .iter()
+            .map(|e| (e, e.health.lock().unwrap().clone()))
+            .filter(|(_, h)| {
+                !write
+                    || (h.healthy
+                        && h.block_lag
+                            .is_some_and(|lag| lag <= self.inner.config.max_block_lag))
+            })
+            .collect();
+        if candidates.is_empty() {
+            return Err(FailoverTransportError::NoSyncedEndpoint);
+        }
+
+        // Stable sort keeps the configured order as the tie-breaker
+        candidates.sort_by_key(|(_, h)| {
+            (
+                !h.healthy,
+                h.block_lag.unwrap_or(u64::MAX),
+                h.consecutive_failures,
+            )
+        });
+        Ok(candidates.into_iter().map(|(e, _)| e).collect())
+    }
+
+    fn record_success(&self, endpoint: &Endpoint) {
+        let mut health = endpoint.health.lock().unwrap();
+        health.healthy = true;
+        health.consecutive_failures = 0;
+        health.last_error = None;
+    }
+
+    fn record_failure(&self, endpoint: &Endpoint, error: String) {
+        let mut health = endpoint.health.lock().unwrap();
+        health.consecutive_failures += 1;
+        health.last_error = Some(error);
+        if health.consecutive_failures >= self.inner.config.failure_threshold {
+            health.healthy = false;
+        }
+    }
+}
+
+fn is_write_method(method: JsonRpcMethod) -> bool {
+    matches!(
+        method,
+        JsonRpcMethod::AddInvokeTransaction
+            | JsonRpcMethod::AddDeclareTransaction
+            | JsonRpcMethod::AddDeployAccountTransaction
+    )
+}
+
+fn is_write_request(request: &ProviderRequestData) -> bool {
+    matches!(
+        request,
+        ProviderRequestData::AddInvokeTransaction(_)
+            | ProviderRequestData::AddDeclareTransaction(_)
+            | ProviderRequestData::AddDeployAccountTransaction(_)
+    )
+}
+
+enum Request<'a> {
+    Single {
+        method: JsonRpcMethod,
+        params: &'a serde_json::Value,
+    },
+    Batch(&'a [ProviderRequestData]),
+}
+
+impl Request<'_> {
+    fn is_write(&self) -> bool {
+        match self {
+            Self::Single { method, .. } => is_write_method(*method),
+            Self::Batch(requests) => requests.iter().any(is_write_request),
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        match self {
+            Self::Single { .. } => 1,
+            Self::Batch(requests) => requests.len() as u32,
+        }
+    }
+}
+
+impl FailoverTransport {
+    /// Sends the request to the candidate endpoints in order until one answers, and repeats the
+    /// whole round with backoff while the failures are retryable.
+    async fn dispatch(
+        &self,
+        request: Request<'_>,
+    ) -> Result<Vec<JsonRpcResponse<serde_json::Value>>, FailoverTransportError> {
+        let policy = &self.inner.config.retry;
+        let mut round = 0;
+        loop {
+            let mut last_failure = None;
+            for (ix, endpoint) in self
+                .candidates(request.is_write())
+                .await?
+                .into_iter()
+                .enumerate()
+            {
+                if ix > 0 {
+                    self.inner.metrics.record_failover();
+                }
+                if let Some(limiter) = &self.inner.config.rate_limiter {
+                    let waited = limiter.acquire(request.cost()).await;
+                    self.inner.metrics.record_throttle(waited);
+                }
+                self.inner.metrics.record_request();
+
+                let result = match &request {
+                    Request::Single { method, params } => endpoint
+                        .transport
+                        .send_request(*method, params)
+                        .await
+                        .map(|response| vec![response]),
+                    Request::Batch(requests) => endpoint.transport.send_requests(*requests).await,
+                };
+                match result {
+                    Ok(responses) => {
+                        let throttled = responses.iter().find_map(|response| match response {
+                            JsonRpcResponse::Error { error, .. }
+                                if policy.is_retryable_rpc_error(error) =>
+                            {
+                                Some(error.message.clone())
+                            }
+                            _ => None,
+                        });
+                        match throttled {
+                            Some(message) => {
+                                self.record_failure(endpoint, message);
+                                last_failure = Some(Ok(responses));
+                            }
+                            None => {
+                                self.record_success(endpoint);
+                                return Ok(responses);
+                            }
+                        }
+                    }
+                    Err(source) => {
+                        self.record_failure(endpoint, source.to_string());
+                        // A transaction may have been accepted even though its response was
+                        // lost, and sending it again would only fail as a duplicate
+                        let delivered =
+                            !matches!(&source, HttpTransportError::Reqwest(e) if e.is_connect());
+                        if request.is_write() && delivered {
+                            return Err(FailoverTransportError::Transport {
+                                url: endpoint.health.lock().unwrap().url.clone(),
+                                source,
+                            });
+                        }
+                        let retryable = policy.is_retryable_transport_error(&source);
+                        last_failure = Some(Err((endpoint, source, retryable)));
+                    }
+                }
+            }
+
+            round += 1;
+            let retryable = !matches!(last_failure, Some(Err((_, _, false))));
+            if !retryable || round >= policy.max_attempts {
+                return match last_failure.expect("at least one candidate was tried") {
+                    // Out of retries, hand the throttling error to the caller as is
+                    Ok(responses) => Ok(responses),
+                    Err((endpoint, source, _)) => Err(FailoverTransportError::Transport {
+                        url: endpoint.health.lock().unwrap().url.clone(),
+                        source,
+                    }),
+                };
+            }
+            self.inner.metrics.record_retry();
+            tokio::time::sleep(policy.backoff(round)).await;
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcTransport for FailoverTransport {
+    type Error = FailoverTransportError;
+
+    async fn send_request<P, R>(
+        &self,
+        method: JsonRpcMethod,
+        params: P,
+    ) -> Result<JsonRpcResponse<R>, Self::Error>
+    where
+        P: Serialize + Send + Sync,
+        R: DeserializeOwned,
+    {
+        let params = serde_json::to_value(params).map_err(FailoverTransportError::Json)?;
+        let response = self
+            .dispatch(Request::Single {
+                method,
+                params: &params,
+            })
+            .await?
+            .pop()
+            .expect("single request has a single response");
+
+        Ok(match response {
+            JsonRpcResponse::Success { id, result } => JsonRpcResponse::Success {
+                id,
+                result: serde_json::from_value(result).map_err(FailoverTransportError::Json)?,
+            },
+            JsonRpcResponse::Error { id, error } => JsonRpcResponse::Error { id, error },
+        })
+    }
+
+    async fn send_requests<R>(
+        &self,
+        requests: R,
+    ) -> Result<Vec<JsonRpcResponse<serde_json::Value>>, Self::Error>
+    where
+        R: AsRef<[ProviderRequestData]> + Send + Sync,
+    {
+        self.dispatch(Request::Batch(requests.as_ref())).await
+    }
+}
+
+#[tokio::test]
+async fn test_reads_fail_over_to_live_endpoint() {
+    use crate::test_utils::{MockRpc, dead_url};
+
+    let live = MockRpc::start(|method, _| match method {
+        "starknet_blockNumber" => Ok(serde_json::json!(100)),
+        "starknet_chainId" => Ok(serde_json::json!("0x534e5f5345504f4c4941")),
+        _ => Err(serde_json::json!({"code": -32601, "message": "Method not found"})),
+    })
+    .await;
+    let dead = dead_url();
+    let transport = FailoverTransport::new(&[&dead, &live.url]);
+
+    let response = transport
+        .send_request::<_, String>(JsonRpcMethod::ChainId, serde_json::json!([]))
+        .await
+        .unwrap();
+    assert!(matches!(response, JsonRpcResponse::Success { .. }));
+
+    let health = transport.health();
+    assert!(health[0].last_error.is_some());
+    assert_eq!(health[1].latest_block, Some(100));
+    assert_eq!(health[1].block_lag, Some(0));
+}
+
+#[tokio::test]
+async fn test_transactions_skip_lagging_endpoints() {
+    use crate::test_utils::MockRpc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let rpc = |head: u64, up: Arc<AtomicBool>| {
+        move |method: &str, _: &serde_json::Value| match method {
+            _ if !up.load(Ordering::SeqCst) => {
+                Err(serde_json::json!({"code": -32603, "message": "Internal error"}))
+            }
+            "starknet_blockNumber" => Ok(serde_json::json!(head)),
+            "starknet_addInvokeTransaction" => Ok(serde_json::json!({"transaction_hash": "0x1"})),
+            _ => Err(serde_json::json!({"code": -32601, "message": "Method not found"})),
+        }
+    };
+    let synced_up = Arc::new(AtomicBool::new(true));
+    let lagging = MockRpc::start(rpc(90, Arc::new(AtomicBool::new(true)))).await;
+    let synced = MockRpc::start(rpc(100, synced_up.clone())).await;
+    let transport = FailoverTransport::with_config(
+        &[&lagging.url, &synced.url],
+        FailoverConfig {
+            failure_threshold: 1,
+            ..Default::default()
+        },
+    );
+
+    transport
+        .send_request::<_, serde_json::Value>(
+            JsonRpcMethod::AddInvokeTransaction,
+            serde_json::json!({}),
+        )
+        .await
+        .unwrap();
+    let invokes = |rpc: &MockRpc| {
+        rpc.calls()
+            .iter()
+            .filter(|m| *m == "starknet_addInvokeTransaction")
+            .count()
+    };
+    assert_eq!(invokes(&lagging), 0);
+    assert_eq!(invokes(&synced), 1);
+
+    // The head is recomputed from every round's answers, so once the node that was ahead goes
+    // down the remaining one is no longer measured against a block it may never reach
+    synced_up.store(false, Ordering::SeqCst);
+    transport.check_health().await;
+    let health = transport.health();
+    assert_eq!(health[0].block_lag, Some(0));
+    assert_eq!(health[1].block_lag, None);
+    transport
+        .send_request::<_, serde_json::Value>(
+            JsonRpcMethod::AddInvokeTransaction,
+            serde_json::json!({}),
+        )
+        .await
+        .unwrap();
+    assert_eq!(invokes(&lagging), 1);
+}
+
+#[tokio::test]
+async fn test_health_checks_do_not_stall_requests() {
+    use crate::test_utils::MockRpc;
+
+    let live = MockRpc::start(|method, _| match method {
+        "starknet_blockNumber" => Ok(serde_json::json!(100)),
+        _ => Ok(serde_json::json!("0x534e5f5345504f4c4941")),
+    })
+    .await;
+    // Accepts connections but never answers
+    let blackhole = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let blackhole_url = format!("http://{}", blackhole.local_addr().unwrap());
+    let transport = FailoverTransport::with_config(
+        &[&blackhole_url, &live.url],
+        FailoverConfig {
+            health_check_interval: Duration::ZERO,
+            health_check_timeout: Duration::from_millis(200),
+            failure_threshold: 1,
+            ..Default::default()
+        },
+    );
+    let chain_id =
+        || transport.send_request::<_, String>(JsonRpcMethod::ChainId, serde_json::json!([]));
+
+    // The first request waits for the probe of the hung endpoint to time out
+    chain_id().await.unwrap();
+    let health = transport.health();
+    assert!(!health[0].healthy);
+    assert!(health[0].last_error.is_some());
+    assert_eq!(health[1].block_lag, Some(0));
+
+    // Stale health is refreshed in the background by a single check
+    let started = Instant::now();
+    for response in futures::future::join_all((0..5).map(|_| chain_id())).await {
+        response.unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_millis(200));
+    let probes = live
+        .calls()
+        .iter()
+        .filter(|m| *m == "starknet_blockNumber")
+        .count();
+    assert!(probes <= 2, "{probes} health checks");
+}
+
+#[tokio::test]
+async fn test_transactions_are_not_resent_after_a_lost_response() {
+    use crate::test_utils::MockRpc;
+
+    let rpc = |lose_response: bool| {
+        move |method: &str, _: &serde_json::Value| match method {
+            "starknet_blockNumber" => Ok(serde_json::json!(100)),
+            "starknet_addInvokeTransaction" if lose_response => Err(serde_json::Value::Null),
+            "starknet_addInvokeTransaction" => Ok(serde_json::json!({"transaction_hash": "0x1"})),
+            _ => Err(serde_json::json!({"code": -32601, "message": "Method not found"})),
+        }
+    };
+    let lossy = MockRpc::start(rpc(true)).await;
+    let backup = MockRpc::start(rpc(false)).await;
+    let transport = FailoverTransport::with_config(
+        &[&lossy.url, &backup.url],
+        FailoverConfig {
+            retry: RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+    let invoke = || {
+        transport.send_request::<_, serde_json::Value>(
+            JsonRpcMethod::AddInvokeTransaction,
+            serde_json::json!({}),
+        )
+    };
+    let invokes = |rpc: &MockRpc| {
+        rpc.calls()
+            .iter()
+            .filter(|m| *m == "starknet_addInvokeTransaction")
+            .count()
+    };
+
+    assert!(matches!(
+        invoke().await,
+        Err(FailoverTransportError::Transport { .. })
+    ));
+    assert_eq!(invokes(&lossy), 1);
+    assert_eq!(invokes(&backup), 0);
+    assert_eq!(transport.metrics().retries, 0);
+}
+
+#[tokio::test]
+async fn test_throttled_requests_are_retried() {
+    use crate::test_utils::MockRpc;
+    use std::sync::atomic::AtomicU32;
+
+    let throttled = Arc::new(AtomicU32::new(2));
+    let remaining = throttled.clone();
+    let rpc = MockRpc::start(move |method, _| match method {
+        "starknet_blockNumber" => Ok(serde_json::json!(100)),
+        _ if remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok() =>
+        {
+            Err(serde_json::json!({"code": 429, "message": "Too many requests"}))
+        }
+        _ => Ok(serde_json::json!("0x534e5f5345504f4c4941")),
+    })
+    .await;
+    let transport = FailoverTransport::with_config(
+        &[&rpc.url],
+        FailoverConfig {
+            retry: RetryPolicy {
+                initial_backoff: Duration::from_millis(1),
+                ..Default::default()
+            },
+            rate_limiter: Some(RateLimiter::new(1000.0, 10)),
+            ..Default::default()
+        },
+    );
+
+    let response = transport
+        .send_request::<_, String>(JsonRpcMethod::ChainId, serde_json::json!([]))
+        .await
+        .unwrap();
+    assert!(matches!(response, JsonRpcResponse::Success { .. }));
+    assert_eq!(transport.metrics().retries, 2);
+
+    // Once attempts run out the throttling error reaches the caller
+    throttled.store(5, Ordering::SeqCst);
+    let response = transport
+        .send_request::<_, String>(JsonRpcMethod::ChainId, serde_json::json!([]))
+        .await
+        .unwrap();
+    assert!(matches!(
+        response,
+        JsonRpcResponse::Error { error, .. } if error.code == 429
+    ));
+    assert_eq!(transport.metrics().retries, 4);
+}
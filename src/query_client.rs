@@ -1,82 +1,100 @@
-use crate::types::{ExecutionResult, Transaction, TransactionStatus};
-use serde::{Deserialize, Serialize};
+use crate::{
+    provider::FailoverTransport,
+    spec::{BlockHeader, BlockStatus, EventsPage, SpecVersion, TransactionReceipt},
+    types::{ExecutionResult, Transaction, TransactionStatus},
+};
+use serde::Serialize;
 use serde_json::Value;
 use starknet::{
-    core::types::Felt,
+    core::types::{
+        BlockId, EventFilter, EventFilterWithPage, Felt, FunctionCall, ResultPageRequest,
+        StarknetError,
+        requests::{
+            CallRequest, GetBlockWithTxHashesRequest, GetBlockWithTxHashesRequestRef,
+            GetEventsRequestRef, GetTransactionByHashRequest, GetTransactionReceiptRequest,
+            GetTransactionReceiptRequestRef, GetTransactionStatusRequest,
+            GetTransactionStatusRequestRef,
+        },
+    },
     providers::{
-        Provider, Url,
-        jsonrpc::{HttpTransport, JsonRpcClient},
+        Provider, ProviderError, ProviderRequestData,
+        jsonrpc::{JsonRpcClient, JsonRpcMethod, JsonRpcResponse, JsonRpcTransport},
     },
 };
-
-#[derive(Debug, Serialize)]
-struct JsonRpcRequest {
-    jsonrpc: String,
-    method: String,
-    params: Value,
-    id: u64,
-}
-
-#[allow(dead_code)]
-#[derive(Debug, Deserialize)]
-struct JsonRpcResponse {
-    jsonrpc: String,
-    id: u64,
-    #[serde(flatten)]
-    result: JsonRpcResult,
-}
-
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
-enum JsonRpcResult {
-    Success { result: TransactionStatusResult },
-    Error { error: JsonRpcError },
-}
-
-#[derive(Debug, Deserialize)]
-struct TransactionStatusResult {
-    #[serde(default)]
-    execution_status: Option<String>,
-    finality_status: String,
-    #[serde(default)]
-    failure_reason: Option<String>,
-}
-
-#[derive(Debug, Deserialize)]
-struct JsonRpcError {
-    code: i32,
-    message: String,
-}
+use tokio::sync::OnceCell;
 
 pub struct QueryClient {
-    provider: JsonRpcClient<HttpTransport>,
-    rpc_url: String,
-    client: reqwest::Client,
+    provider: JsonRpcClient<FailoverTransport>,
+    // Kept alongside the provider so responses can be decoded into SDK-owned types the
+    // provider's own models cannot represent
+    transport: FailoverTransport,
+    spec_version: OnceCell<SpecVersion>,
 }
 
 impl QueryClient {
+    /// Providers commonly reject larger JSON-RPC batches
+    pub const MAX_BATCH_SIZE: usize = 100;
+
     pub fn new(url: &str) -> Self {
-        let provider =
-            JsonRpcClient::new(HttpTransport::new(Url::parse(url).expect("Invalid URL")));
-        let client = reqwest::Client::new();
+        Self::from_transport(FailoverTransport::new(&[url]))
+    }
+
+    pub fn from_transport(transport: FailoverTransport) -> Self {
+        let provider = JsonRpcClient::new(transport.clone());
         Self {
             provider,
-            rpc_url: url.to_string(),
-            client,
+            transport,
+            spec_version: OnceCell::new(),
         }
     }
 
+    /// Like [`QueryClient::new`], but detects the node's spec version up front so an
+    /// unsupported node is rejected at startup rather than on first use.
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let client = Self::new(url);
+        client.spec_version().await?;
+        Ok(client)
+    }
+
+    pub fn transport(&self) -> &FailoverTransport {
+        &self.transport
+    }
+
+    /// The underlying provider, e.g. for the view functions in [`crate::bindings`].
+    pub fn provider(&self) -> &JsonRpcClient<FailoverTransport> {
+        &self.provider
+    }
+
+    /// The JSON-RPC spec version reported by `starknet_specVersion`, queried once and cached.
+    pub async fn spec_version(&self) -> anyhow::Result<SpecVersion> {
+        self.spec_version
+            .get_or_try_init(|| async {
+                let version = self.provider.spec_version().await?;
+                version.parse()
+            })
+            .await
+            .copied()
+    }
+
     pub async fn get_transaction_receipt(&self, tx_hash: &str) -> anyhow::Result<ExecutionResult> {
+        let receipt = self.get_receipt(tx_hash).await.map_err(|e| {
+            anyhow::anyhow!("Failed to get transaction receipt with error: {:?}", e)
+        })?;
+        Ok(receipt.execution_result())
+    }
+
+    pub async fn get_receipt(&self, tx_hash: &str) -> anyhow::Result<TransactionReceipt> {
         let tx_hash = Felt::from_hex(tx_hash)?;
-        let exe_res = self
-            .provider
-            .get_transaction_receipt(tx_hash)
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to get transaction receipt with error: {:?}", e))?
-            .receipt
-            .execution_result()
-            .clone();
-        Ok(exe_res)
+        let version = self.spec_version().await?;
+        let receipt = self
+            .request(
+                JsonRpcMethod::GetTransactionReceipt,
+                GetTransactionReceiptRequestRef {
+                    transaction_hash: &tx_hash,
+                },
+            )
+            .await?;
+        version.parse_receipt(receipt)
     }
 
     pub async fn get_transaction(&self, tx_hash: &str) -> anyhow::Result<Transaction> {
@@ -86,79 +104,337 @@ impl QueryClient {
     }
 
     pub async fn get_transaction_status(&self, tx_hash: &str) -> anyhow::Result<TransactionStatus> {
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            method: "starknet_getTransactionStatus".to_string(),
-            params: serde_json::json!({
-                "transaction_hash": tx_hash
-            }),
-            id: 1,
-        };
-
-        let response = self
-            .client
-            .post(&self.rpc_url)
-            .json(&request)
-            .send()
+        let tx_hash = Felt::from_hex(tx_hash)?;
+        let version = self.spec_version().await?;
+        let status = self
+            .request(
+                JsonRpcMethod::GetTransactionStatus,
+                GetTransactionStatusRequestRef {
+                    transaction_hash: &tx_hash,
+                },
+            )
             .await?;
+        version.parse_transaction_status(status)
+    }
 
-        let json_response: JsonRpcResponse = response.json().await?;
-
-        match json_response.result {
-            JsonRpcResult::Success { result } => {
-                // Map the JSON response to Starknet's TransactionStatus
-
-                // Handle different response formats based on finality_status
-                match result.finality_status.as_str() {
-                    "REJECTED" => {
-                        let reason = result
-                            .failure_reason
-                            .unwrap_or_else(|| "Transaction rejected".to_string());
-                        Ok(TransactionStatus::Rejected { reason })
-                    }
-                    "ACCEPTED_ON_L1" | "ACCEPTED_ON_L2" => {
-                        if let Some(exec_status) = result.execution_status {
-                            let execution_result = match exec_status.as_str() {
-                                "SUCCEEDED" => ExecutionResult::Succeeded,
-                                "REVERTED" => {
-                                    // For reverted transactions, include the failure reason if available
-                                    let reason = result
-                                        .failure_reason
-                                        .unwrap_or_else(|| "Transaction reverted".to_string());
-                                    ExecutionResult::Reverted { reason }
-                                }
-                                _ => {
-                                    return Err(anyhow::anyhow!(
-                                        "Unknown execution status: {}",
-                                        exec_status
-                                    ));
-                                }
-                            };
-                            Ok(TransactionStatus::AcceptedOnL2(execution_result))
-                        } else {
-                            Ok(TransactionStatus::Received)
-                        }
-                    }
-                    _ => {
-                        // For other statuses, default to Received
-                        Ok(TransactionStatus::Received)
-                    }
-                }
-            }
-            JsonRpcResult::Error { error } => {
-                if error.code == 29 {
-                    Err(anyhow::anyhow!(
-                        "Transaction hash not found: {}",
-                        error.message
-                    ))
-                } else {
-                    Err(anyhow::anyhow!(
-                        "RPC error {}: {}",
-                        error.code,
-                        error.message
-                    ))
-                }
+    pub async fn get_events(
+        &self,
+        event_filter: EventFilter,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> anyhow::Result<EventsPage> {
+        let version = self.spec_version().await?;
+        let page = self
+            .request(
+                JsonRpcMethod::GetEvents,
+                GetEventsRequestRef {
+                    filter: &EventFilterWithPage {
+                        event_filter,
+                        result_page_request: ResultPageRequest {
+                            continuation_token,
+                            chunk_size,
+                        },
+                    },
+                },
+            )
+            .await?;
+        version.parse_events_page(page)
+    }
+
+    /// Events of the block that is still being built, matching `address` and `keys` like
+    /// [`EventFilter`] does.
+    pub async fn get_pending_events(
+        &self,
+        address: Felt,
+        keys: Vec<Vec<Felt>>,
+        continuation_token: Option<String>,
+        chunk_size: u64,
+    ) -> anyhow::Result<EventsPage> {
+        let version = self.spec_version().await?;
+        // starknet-rs has no tag for v0.9's pre-confirmed block, so the filter is built by hand
+        let mut filter = serde_json::json!({
+            "from_block": version.pending_block_tag(),
+            "to_block": version.pending_block_tag(),
+            "address": address,
+            "keys": keys,
+            "chunk_size": chunk_size,
+        });
+        if let Some(token) = continuation_token {
+            filter["continuation_token"] = token.into();
+        }
+        let page = self
+            .request(
+                JsonRpcMethod::GetEvents,
+                serde_json::json!({ "filter": filter }),
+            )
+            .await?;
+        version.parse_events_page(page)
+    }
+
+    /// Number of the newest block accepted on L1. `known` must be a block already known to be
+    /// accepted on L1 (0 if none); older nodes have no tag for it, so it is binary searched from
+    /// there on.
+    pub async fn l1_accepted_block_number(&self, known: u64) -> anyhow::Result<u64> {
+        let version = self.spec_version().await?;
+        if version >= SpecVersion::V0_9 {
+            let block = self
+                .request(
+                    JsonRpcMethod::GetBlockWithTxHashes,
+                    serde_json::json!({ "block_id": "l1_accepted" }),
+                )
+                .await?;
+            return version
+                .parse_block_header(block)?
+                .block_number
+                .ok_or_else(|| anyhow::anyhow!("L1 accepted block without block number"));
+        }
+
+        let (mut low, mut high) = (known, self.block_number().await?);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            let header = self.get_block_header(BlockId::Number(mid)).await?;
+            if header.status == Some(BlockStatus::AcceptedOnL1) {
+                low = mid;
+            } else {
+                high = mid - 1;
             }
         }
+        Ok(low)
+    }
+
+    pub async fn get_block_header(&self, block_id: BlockId) -> anyhow::Result<BlockHeader> {
+        let version = self.spec_version().await?;
+        let block = self
+            .request(
+                JsonRpcMethod::GetBlockWithTxHashes,
+                GetBlockWithTxHashesRequestRef {
+                    block_id: &block_id,
+                },
+            )
+            .await?;
+        version.parse_block_header(block)
     }
+
+    pub async fn block_number(&self) -> anyhow::Result<u64> {
+        Ok(self.provider.block_number().await?)
+    }
+
+    /// Nonce of `address` including transactions that are not in a block yet.
+    pub async fn get_pending_nonce(&self, address: Felt) -> anyhow::Result<Felt> {
+        let version = self.spec_version().await?;
+        let nonce = self
+            .request(
+                JsonRpcMethod::GetNonce,
+                serde_json::json!({
+                    "block_id": version.pending_block_tag(),
+                    "contract_address": address,
+                }),
+            )
+            .await?;
+        Ok(serde_json::from_value(nonce)?)
+    }
+
+    /// Statuses of many transactions in as few HTTP requests as possible. The outer error is a
+    /// transport failure, the inner ones are per transaction (e.g. an unknown hash).
+    pub async fn get_transaction_statuses(
+        &self,
+        tx_hashes: &[&str],
+    ) -> anyhow::Result<Vec<anyhow::Result<TransactionStatus>>> {
+        let version = self.spec_version().await?;
+        let requests = tx_hashes
+            .iter()
+            .map(|tx_hash| {
+                Ok(ProviderRequestData::GetTransactionStatus(
+                    GetTransactionStatusRequest {
+                        transaction_hash: Felt::from_hex(tx_hash)?,
+                    },
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(self
+            .batch(requests)
+            .await?
+            .into_iter()
+            .map(|status| version.parse_transaction_status(status?))
+            .collect())
+    }
+
+    pub async fn get_transactions(
+        &self,
+        tx_hashes: &[&str],
+    ) -> anyhow::Result<Vec<anyhow::Result<Transaction>>> {
+        let requests = tx_hashes
+            .iter()
+            .map(|tx_hash| {
+                Ok(ProviderRequestData::GetTransactionByHash(
+                    GetTransactionByHashRequest {
+                        transaction_hash: Felt::from_hex(tx_hash)?,
+                    },
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(self
+            .batch(requests)
+            .await?
+            .into_iter()
+            .map(|tx| Ok(serde_json::from_value(tx?)?))
+            .collect())
+    }
+
+    pub async fn get_receipts(
+        &self,
+        tx_hashes: &[&str],
+    ) -> anyhow::Result<Vec<anyhow::Result<TransactionReceipt>>> {
+        let version = self.spec_version().await?;
+        let requests = tx_hashes
+            .iter()
+            .map(|tx_hash| {
+                Ok(ProviderRequestData::GetTransactionReceipt(
+                    GetTransactionReceiptRequest {
+                        transaction_hash: Felt::from_hex(tx_hash)?,
+                    },
+                ))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(self
+            .batch(requests)
+            .await?
+            .into_iter()
+            .map(|receipt| version.parse_receipt(receipt?))
+            .collect())
+    }
+
+    pub async fn get_block_headers(
+        &self,
+        block_ids: &[BlockId],
+    ) -> anyhow::Result<Vec<anyhow::Result<BlockHeader>>> {
+        let version = self.spec_version().await?;
+        let requests = block_ids
+            .iter()
+            .map(|block_id| {
+                ProviderRequestData::GetBlockWithTxHashes(GetBlockWithTxHashesRequest {
+                    block_id: *block_id,
+                })
+            })
+            .collect();
+        Ok(self
+            .batch(requests)
+            .await?
+            .into_iter()
+            .map(|block| version.parse_block_header(block?))
+            .collect())
+    }
+
+    pub async fn call_batch(
+        &self,
+        calls: &[FunctionCall],
+        block_id: BlockId,
+    ) -> anyhow::Result<Vec<anyhow::Result<Vec<Felt>>>> {
+        let requests = calls
+            .iter()
+            .map(|call| {
+                ProviderRequestData::Call(CallRequest {
+                    request: call.clone(),
+                    block_id,
+                })
+            })
+            .collect();
+        Ok(self
+            .batch(requests)
+            .await?
+            .into_iter()
+            .map(|result| Ok(serde_json::from_value(result?)?))
+            .collect())
+    }
+
+    async fn request<P>(&self, method: JsonRpcMethod, params: P) -> anyhow::Result<Value>
+    where
+        P: Serialize + Send + Sync,
+    {
+        into_result(self.transport.send_request(method, params).await?)
+    }
+
+    /// Sends the requests as concurrent JSON-RPC batches of at most [`Self::MAX_BATCH_SIZE`] calls
+    /// and returns the results in request order.
+    async fn batch(
+        &self,
+        requests: Vec<ProviderRequestData>,
+    ) -> anyhow::Result<Vec<anyhow::Result<Value>>> {
+        let batches = requests
+            .chunks(Self::MAX_BATCH_SIZE)
+            .map(|chunk| async move {
+                let responses = self.transport.send_requests(chunk).await?;
+                if responses.len() != chunk.len() {
+                    anyhow::bail!(
+                        "Batch of {} requests answered with {} responses",
+                        chunk.len(),
+                        responses.len()
+                    );
+                }
+                Ok(responses)
+            });
+        let responses = futures::future::try_join_all(batches).await?;
+        Ok(responses.into_iter().flatten().map(into_result).collect())
+    }
+}
+
+fn into_result(response: JsonRpcResponse<Value>) -> anyhow::Result<Value> {
+    match response {
+        JsonRpcResponse::Success { result, .. } => Ok(result),
+        JsonRpcResponse::Error { error, .. } => match StarknetError::try_from(&error) {
+            Ok(error) => Err(ProviderError::StarknetError(error).into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "RPC error {}: {}",
+                error.code,
+                error.message
+            )),
+        },
+    }
+}
+
+#[tokio::test]
+async fn test_transaction_statuses_are_batched() {
+    use crate::test_utils::MockRpc;
+
+    let rpc = MockRpc::start(|method, params| match method {
+        "starknet_specVersion" => Ok(serde_json::json!("0.8.1")),
+        "starknet_blockNumber" => Ok(serde_json::json!(100)),
+        "starknet_getTransactionStatus" if params["transaction_hash"] == "0x1" => {
+            Ok(serde_json::json!({"finality_status": "ACCEPTED_ON_L1", "execution_status": "SUCCEEDED"}))
+        }
+        "starknet_getTransactionStatus" => {
+            Err(serde_json::json!({"code": 29, "message": "Transaction hash not found"}))
+        }
+        _ => Err(serde_json::json!({"code": -32601, "message": "Method not found"})),
+    })
+    .await;
+    let client = QueryClient::new(&rpc.url);
+
+    let statuses = client
+        .get_transaction_statuses(&["0x1", "0x2", "0x1"])
+        .await
+        .unwrap();
+    assert_eq!(statuses.len(), 3);
+    assert!(statuses[0].as_ref().unwrap().is_accepted_on_l1());
+    assert!(matches!(
+        statuses[1]
+            .as_ref()
+            .unwrap_err()
+            .downcast_ref::<ProviderError>(),
+        Some(ProviderError::StarknetError(
+            StarknetError::TransactionHashNotFound
+        ))
+    ));
+    assert!(statuses[2].is_ok());
+
+    // Three statuses, one HTTP round trip
+    let calls = rpc.calls();
+    assert_eq!(
+        calls
+            .iter()
+            .filter(|m| *m == "starknet_getTransactionStatus")
+            .count(),
+        3
+    );
+    assert_eq!(client.transport().metrics().requests, 2);
 }
@@ -0,0 +1,624 @@
+//! Audits the bridge over a block range: every Mint must come from exactly one expected Bitcoin
+//! deposit, and the token supply must change by exactly what was minted minus what was burned.
+
+use crate::{
+    bindings::bitvm_bridge::{self, Peg},
+    events::{BurnEvent, EventMeta, MintEvent, WatchedContract, fetch_events, order_events},
+    provider::FailoverTransport,
+    query_client::QueryClient,
+    spec::{BlockHeader, EmittedEvent},
+    types::{BtcAmount, Transaction, TransactionEvent},
+    utils::parse_event,
+};
+use serde::{Deserialize, Serialize};
+use starknet::core::{
+    codec::Decode,
+    types::{BlockId, Felt, InvokeTransaction},
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// A Bitcoin deposit that should have been minted on Starknet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BtcDeposit {
+    /// As displayed by Bitcoin tools
+    pub txid: String,
+    pub vout: u32,
+    /// Starknet recipient
+    pub to: String,
+    pub amount: BtcAmount,
+}
+
+impl BtcDeposit {
+    pub fn outpoint(&self) -> String {
+        format!("{}:{}", self.txid.to_lowercase(), self.vout)
+    }
+}
+
+/// A Mint event with the outpoint its peg claimed, when it could be recovered from the mint
+/// transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObservedMint {
+    pub event: MintEvent,
+    pub outpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    /// An expected deposit was not minted
+    MissingMint,
+    /// A mint claims an outpoint that is not among the expected deposits
+    UnexpectedMint,
+    /// The outpoint of a mint could not be recovered from its transaction
+    UnknownOutpoint,
+    /// An outpoint was minted more than once
+    DuplicateMint,
+    AmountMismatch,
+    RecipientMismatch,
+    /// The supply changed by something other than mints minus burns
+    SupplyMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    pub outpoint: Option<String>,
+    /// Id of the Mint event, see [`EventMeta::id`]
+    pub event_id: Option<String>,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub mints: u64,
+    pub minted: BtcAmount,
+    pub burns: u64,
+    pub burned: BtcAmount,
+    /// Total supply at the end of block `from_block - 1`
+    pub supply_before: BtcAmount,
+    /// Total supply at the end of block `to_block`
+    pub supply_after: BtcAmount,
+    pub findings: Vec<Finding>,
+}
+
+impl ReconciliationReport {
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// The findings, one per row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = "kind,outpoint,event_id,detail\n".to_string();
+        for finding in &self.findings {
+            let kind = serde_json::to_value(finding.kind).unwrap();
+            let row = [
+                kind.as_str().unwrap_or_default(),
+                finding.outpoint.as_deref().unwrap_or_default(),
+                finding.event_id.as_deref().unwrap_or_default(),
+                &finding.detail,
+            ];
+            let row: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Compares what was minted and burned in blocks `from_block..=to_block` with the expected
+/// deposits and the supply at both ends of the range.
+pub fn reconcile(
+    from_block: u64,
+    to_block: u64,
+    mints: &[ObservedMint],
+    burns: &[BurnEvent],
+    deposits: &[BtcDeposit],
+    supply_before: BtcAmount,
+    supply_after: BtcAmount,
+) -> anyhow::Result<ReconciliationReport> {
+    let mut expected = BTreeMap::new();
+    for deposit in deposits {
+        if expected.insert(deposit.outpoint(), deposit).is_some() {
+            anyhow::bail!("Deposit {} is listed twice", deposit.outpoint());
+        }
+    }
+
+    let mut findings = vec![];
+    let mut minted_outpoints: HashMap<&str, &ObservedMint> = HashMap::new();
+    for mint in mints {
+        let event_id = Some(mint.event.meta.id());
+        let Some(outpoint) = &mint.outpoint else {
+            findings.push(Finding {
+                kind: FindingKind::UnknownOutpoint,
+                outpoint: None,
+                event_id,
+                detail: format!("Mint of {} to {}", mint.event.value, mint.event.to),
+            });
+            continue;
+        };
+        if let Some(first) = minted_outpoints.insert(outpoint, mint) {
+            minted_outpoints.insert(outpoint, first);
+            findings.push(Finding {
+                kind: FindingKind::DuplicateMint,
+                outpoint: Some(outpoint.clone()),
+                event_id,
+                detail: format!("Already minted by {}", first.event.meta.id()),
+            });
+            continue;
+        }
+
+        let Some(deposit) = expected.get(outpoint) else {
+            findings.push(Finding {
+                kind: FindingKind::UnexpectedMint,
+                outpoint: Some(outpoint.clone()),
+                event_id,
+                detail: format!("Mint of {} to {}", mint.event.value, mint.event.to),
+            });
+            continue;
+        };
+        if mint.event.value != deposit.amount {
+            findings.push(Finding {
+                kind: FindingKind::AmountMismatch,
+                outpoint: Some(outpoint.clone()),
+                event_id: event_id.clone(),
+                detail: format!("Deposited {}, minted {}", deposit.amount, mint.event.value),
+            });
+        }
+        if !same_address(&deposit.to, &mint.event.to) {
+            findings.push(Finding {
+                kind: FindingKind::RecipientMismatch,
+                outpoint: Some(outpoint.clone()),
+                event_id,
+                detail: format!("Deposited for {}, minted to {}", deposit.to, mint.event.to),
+            });
+        }
+    }
+
+    for (outpoint, deposit) in &expected {
+        if !minted_outpoints.contains_key(outpoint.as_str()) {
+            findings.push(Finding {
+                kind: FindingKind::MissingMint,
+                outpoint: Some(outpoint.clone()),
+                event_id: None,
+                detail: format!("Deposit of {} for {}", deposit.amount, deposit.to),
+            });
+        }
+    }
+
+    let minted = sum(mints.iter().map(|m| m.event.value))?;
+    let burned = sum(burns.iter().map(|b| b.value))?;
+    // Compared as before + minted = after + burned, so neither side goes negative
+    let overflow = || anyhow::anyhow!("Supply plus the minted or burned amount overflows");
+    let supplied = supply_before.checked_add(minted).ok_or_else(overflow)?;
+    if supplied != supply_after.checked_add(burned).ok_or_else(overflow)? {
+        findings.push(Finding {
+            kind: FindingKind::SupplyMismatch,
+            outpoint: None,
+            event_id: None,
+            detail: format!(
+                "Supply went from {supply_before} to {supply_after}, \
+                 but {minted} were minted and {burned} burned"
+            ),
+        });
+    }
+
+    Ok(ReconciliationReport {
+        from_block,
+        to_block,
+        mints: mints.len() as u64,
+        minted,
+        burns: burns.len() as u64,
+        burned,
+        supply_before,
+        supply_after,
+        findings,
+    })
+}
+
+fn sum(mut amounts: impl Iterator<Item = BtcAmount>) -> anyhow::Result<BtcAmount> {
+    amounts.try_fold(BtcAmount::ZERO, |total, amount| {
+        total
+            .checked_add(amount)
+            .ok_or_else(|| anyhow::anyhow!("Total amount overflows"))
+    })
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    match (Felt::from_hex(a), Felt::from_hex(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a.eq_ignore_ascii_case(b),
+    }
+}
+
+/// Collects the bridge's events, mint transactions and supply from a node and reconciles them.
+pub struct Reconciler {
+    query_client: QueryClient,
+    bridge_contract: Felt,
+    chunk_size: u64,
+}
+
+impl Reconciler {
+    pub fn new(url: &str, bridge_contract: &str) -> anyhow::Result<Self> {
+        Self::from_transport(FailoverTransport::new(&[url]), bridge_contract)
+    }
+
+    pub fn from_transport(
+        transport: FailoverTransport,
+        bridge_contract: &str,
+    ) -> anyhow::Result<Self> {
+        let bridge_contract = Felt::from_hex(bridge_contract).map_err(|_| {
+            anyhow::anyhow!("Invalid bitvm bridge contract address {bridge_contract}")
+        })?;
+        Ok(Self {
+            query_client: QueryClient::from_transport(transport),
+            bridge_contract,
+            chunk_size: 100,
+        })
+    }
+
+    pub async fn report(
+        &self,
+        from_block: u64,
+        to_block: u64,
+        deposits: &[BtcDeposit],
+    ) -> anyhow::Result<ReconciliationReport> {
+        let mut events = fetch_events(
+            &self.query_client,
+            &[WatchedContract::bridge(self.bridge_contract)],
+            from_block,
+            to_block,
+            self.chunk_size,
+        )
+        .await?;
+        let headers = self.block_headers(&events).await?;
+        order_events(&mut events, &headers)?;
+
+        let (mut mints, mut burns) = (vec![], vec![]);
+        for event in events {
+            let header = event.block_number.and_then(|n| headers.get(&n));
+            let meta = EventMeta::locate(&event, header)?;
+            match parse_event(&event)? {
+                TransactionEvent::Mint(mint) => mints.push(MintEvent {
+                    meta,
+                    to: mint.to,
+                    value: mint.value,
+                }),
+                TransactionEvent::Burn(burn) => burns.push(BurnEvent {
+                    meta,
+                    from: burn.from,
+                    btc_addr: burn.btc_addr,
+                    value: burn.value,
+                    fee_rate: burn.fee_rate,
+                    operator_id: burn.operator_id,
+                }),
+                TransactionEvent::Contract(_) => {}
+            }
+        }
+
+        let mints = self.observe(mints).await?;
+        let supply_before = match from_block.checked_sub(1) {
+            Some(block) => self.total_supply(block).await?,
+            None => BtcAmount::ZERO,
+        };
+        let supply_after = self.total_supply(to_block).await?;
+        reconcile(
+            from_block,
+            to_block,
+            &mints,
+            &burns,
+            deposits,
+            supply_before,
+            supply_after,
+        )
+    }
+
+    /// The headers of the blocks holding `events`, by number.
+    async fn block_headers(
+        &self,
+        events: &[EmittedEvent],
+    ) -> anyhow::Result<HashMap<u64, BlockHeader>> {
+        let numbers: BTreeSet<u64> = events.iter().filter_map(|e| e.block_number).collect();
+        let block_ids: Vec<BlockId> = numbers.iter().map(|n| BlockId::Number(*n)).collect();
+        let mut headers = HashMap::new();
+        for (number, header) in numbers
+            .into_iter()
+            .zip(self.query_client.get_block_headers(&block_ids).await?)
+        {
+            headers.insert(number, header?);
+        }
+        Ok(headers)
+    }
+
+    /// Pairs every Mint with the outpoint of a peg of its transaction with the same recipient and
+    /// amount.
+    async fn observe(&self, mints: Vec<MintEvent>) -> anyhow::Result<Vec<ObservedMint>> {
+        let mut tx_hashes: Vec<Felt> = mints.iter().map(|m| m.meta.tx_hash).collect();
+        tx_hashes.sort();
+        tx_hashes.dedup();
+        let hex_hashes: Vec<String> = tx_hashes.iter().map(|h| format!("0x{h:x}")).collect();
+        // One batch at a time, however many mints the range holds
+        let mut transactions = Vec::with_capacity(tx_hashes.len());
+        for chunk in hex_hashes.chunks(QueryClient::MAX_BATCH_SIZE) {
+            let chunk: Vec<&str> = chunk.iter().map(String::as_str).collect();
+            for tx in self.query_client.get_transactions(&chunk).await? {
+                transactions.push(tx?);
+            }
+        }
+        let mut pegs: HashMap<Felt, Vec<Peg>> = tx_hashes
+            .into_iter()
+            .zip(transactions)
+            .map(|(tx_hash, tx)| (tx_hash, minted_pegs(&tx, self.bridge_contract)))
+            .collect();
+
+        Ok(mints
+            .into_iter()
+            .map(|event| {
+                let pegs = pegs.entry(event.meta.tx_hash).or_default();
+                let outpoint = pegs
+                    .iter()
+                    .position(|peg| {
+                        BtcAmount::from_sats(peg.value) == event.value
+                            && same_address(&format!("0x{:x}", peg.to), &event.to)
+                    })
+                    .map(|i| peg_outpoint(&pegs.remove(i)));
+                ObservedMint { event, outpoint }
+            })
+            .collect())
+    }
+
+    async fn total_supply(&self, block: u64) -> anyhow::Result<BtcAmount> {
+        bitvm_bridge::total_supply(
+            self.query_client.provider(),
+            self.bridge_contract,
+            BlockId::Number(block),
+        )
+        .await?
+        .try_into()
+    }
+}
+
+/// One call of an account's `__execute__` calldata, as encoded for Cairo 1 accounts.
+#[derive(Decode)]
+struct ExecuteCall {
+    to: Felt,
+    selector: Felt,
+    calldata: Vec<Felt>,
+}
+
+/// The pegs of the bridge `mint` calls of an invoke transaction; none if its calldata does not
+/// decode.
+fn minted_pegs(tx: &Transaction, bridge_contract: Felt) -> Vec<Peg> {
+    let calldata = match tx {
+        Transaction::Invoke(InvokeTransaction::V1(tx)) => &tx.calldata,
+        Transaction::Invoke(InvokeTransaction::V3(tx)) => &tx.calldata,
+        _ => return vec![],
+    };
+    let Ok(calls) = Vec::<ExecuteCall>::decode(calldata) else {
+        return vec![];
+    };
+    calls
+        .into_iter()
+        .filter(|call| call.to == bridge_contract && call.selector == bitvm_bridge::MINT_SELECTOR)
+        .filter_map(|call| Vec::<Peg>::decode(&call.calldata).ok())
+        .flatten()
+        .collect()
+}
+
+/// `txid:vout` of the output a peg claims, the txid as displayed by Bitcoin tools.
+fn peg_outpoint(peg: &Peg) -> String {
+    let tx_id = &peg.inclusion_proof.tx_id;
+    format!(
+        "{:032x}{:032x}:{}",
+        tx_id.high(),
+        tx_id.low(),
+        peg.tx_out_ix
+    )
+}
+
+#[cfg(test)]
+fn test_mint(tx_hash: u64, to: &str, sats: u64, outpoint: Option<&str>) -> ObservedMint {
+    ObservedMint {
+        event: MintEvent {
+            meta: crate::test_utils::test_meta(tx_hash, 10, 0),
+            to: to.to_string(),
+            value: BtcAmount::from_sats(sats),
+        },
+        outpoint: outpoint.map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+fn test_deposit(txid: &str, vout: u32, to: &str, sats: u64) -> BtcDeposit {
+    BtcDeposit {
+        txid: txid.to_string(),
+        vout,
+        to: to.to_string(),
+        amount: BtcAmount::from_sats(sats),
+    }
+}
+
+#[test]
+fn test_reconcile_clean() {
+    let report = reconcile(
+        10,
+        20,
+        &[test_mint(1, "0x1", 500, Some("aa:0"))],
+        &[],
+        &[test_deposit("AA", 0, "0x0001", 500)],
+        BtcAmount::from_sats(1000),
+        BtcAmount::from_sats(1500),
+    )
+    .unwrap();
+    assert!(report.is_clean(), "{:?}", report.findings);
+    assert_eq!(report.minted, BtcAmount::from_sats(500));
+    assert_eq!(report.to_csv(), "kind,outpoint,event_id,detail\n");
+}
+
+#[test]
+fn test_reconcile_findings() {
+    let deposits = [
+        test_deposit("aa", 0, "0x1", 500),
+        test_deposit("bb", 1, "0x2", 700),
+        test_deposit("cc", 0, "0x3", 100),
+    ];
+    let mints = [
+        test_mint(1, "0x1", 500, Some("aa:0")),
+        test_mint(2, "0x1", 500, Some("aa:0")),
+        test_mint(3, "0x9", 600, Some("bb:1")),
+        test_mint(4, "0x4", 50, Some("dd:2")),
+        test_mint(5, "0x5", 10, None),
+    ];
+    let report = reconcile(
+        10,
+        20,
+        &mints,
+        &[],
+        &deposits,
+        BtcAmount::ZERO,
+        BtcAmount::from_sats(1000),
+    )
+    .unwrap();
+    let kinds: Vec<FindingKind> = report.findings.iter().map(|f| f.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            FindingKind::DuplicateMint,
+            FindingKind::AmountMismatch,
+            FindingKind::RecipientMismatch,
+            FindingKind::UnexpectedMint,
+            FindingKind::UnknownOutpoint,
+            FindingKind::MissingMint,
+            FindingKind::SupplyMismatch,
+        ]
+    );
+    assert_eq!(report.findings[0].event_id.as_deref(), Some("0x2:0"));
+    assert_eq!(report.findings[5].outpoint.as_deref(), Some("cc:0"));
+    assert_eq!(report.minted, BtcAmount::from_sats(1660));
+
+    let csv = report.to_csv();
+    assert_eq!(csv.lines().count(), 8);
+    assert!(csv.contains("\nduplicate_mint,aa:0,0x2:0,Already minted by 0x1:0\n"));
+    assert!(csv.contains("\namount_mismatch,bb:1,0x3:0,\"Deposited 700, minted 600\"\n"));
+    let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+    assert_eq!(json["findings"][6]["kind"], "supply_mismatch");
+
+    assert!(
+        reconcile(
+            0,
+            1,
+            &[],
+            &[],
+            &[deposits[0].clone(), deposits[0].clone()],
+            BtcAmount::ZERO,
+            BtcAmount::ZERO
+        )
+        .is_err()
+    );
+    // Supplies too large to compare are an error, not a clean report
+    assert!(
+        reconcile(
+            0,
+            1,
+            &mints[..1],
+            &[],
+            &[],
+            BtcAmount(u128::MAX),
+            BtcAmount(u128::MAX)
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn test_minted_pegs_outpoint() {
+    use crate::{types::PegContext, utils::btc_txid};
+    use starknet::core::types::InvokeTransactionV1;
+
+    let bridge = Felt::from(0x37fu64);
+    let mut context = PegContext {
+        bitcoin_tx_hash: [0; 32],
+        output_index: 3,
+        ..crate::test_utils::test_context("0x72b1", 500)
+    };
+    context.bitcoin_tx_hash[0] = 0x01;
+    context.bitcoin_tx_hash[31] = 0xff;
+    let txid = btc_txid(&context.bitcoin_tx_hash);
+    let peg = Peg::try_from(context).unwrap();
+    let call = bitvm_bridge::mint(bridge, &[peg]).unwrap();
+
+    let mut calldata = vec![
+        Felt::ONE,
+        call.to,
+        call.selector,
+        Felt::from(call.calldata.len()),
+    ];
+    calldata.extend(call.calldata);
+    let tx = Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+        transaction_hash: Felt::ONE,
+        sender_address: Felt::TWO,
+        calldata,
+        max_fee: Felt::ZERO,
+        signature: vec![],
+        nonce: Felt::ZERO,
+    }));
+    let pegs = minted_pegs(&tx, bridge);
+    assert_eq!(pegs.len(), 1);
+    assert_eq!(peg_outpoint(&pegs[0]), format!("{txid}:3"));
+    assert!(txid.starts_with("ff") && txid.ends_with("01"));
+    assert!(minted_pegs(&tx, Felt::THREE).is_empty());
+}
+
+#[tokio::test]
+async fn test_reconciler_reports_from_node() {
+    use serde_json::json;
+
+    let fixture = crate::test_utils::rpc_fixture();
+    let rpc = crate::test_utils::MockRpc::from_fixture(&fixture, |method, params| match method {
+        // A mint sent without account calldata, so its outpoint is unknown
+        "starknet_getTransactionByHash" => Some(Ok(json!({
+            "type": "INVOKE",
+            "version": "0x1",
+            "transaction_hash": params["transaction_hash"],
+            "sender_address": "0x72b1",
+            "calldata": [],
+            "max_fee": "0x0",
+            "signature": [],
+            "nonce": "0x0"
+        }))),
+        // As much was burned as minted, so the supply did not change
+        "starknet_call" => Some(Ok(json!(["0x1e8480", "0x0"]))),
+        _ => None,
+    })
+    .await;
+
+    assert!(Reconciler::new(&rpc.url, "bridge").is_err());
+    let report = Reconciler::new(&rpc.url, crate::test_utils::BRIDGE_ADDRESS)
+        .unwrap()
+        .report(890861, 890894, &[])
+        .await
+        .unwrap();
+    assert_eq!((report.mints, report.burns), (1, 1));
+    assert_eq!(report.minted, BtcAmount::from_sats(500000));
+    assert_eq!(report.supply_before, BtcAmount::from_sats(2000000));
+    let kinds: Vec<FindingKind> = report.findings.iter().map(|f| f.kind).collect();
+    assert_eq!(kinds, [FindingKind::UnknownOutpoint]);
+    // Located in its receipt, as v0.9 nodes report it
+    assert_eq!(
+        report.findings[0].event_id.as_deref(),
+        Some("0x3387e2e2e6cff4d3e485e7c9343a7ec517c8098a6285f74a30956ecfa63be52:0")
+    );
+}
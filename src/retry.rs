@@ -0,0 +1,220 @@
+use rand::Rng;
+use starknet::providers::jsonrpc::{HttpTransportError, JsonRpcError};
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total attempts per request, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,
+    /// Randomize each delay within its upper half so clients do not retry in lockstep
+    pub jitter: bool,
+    /// JSON-RPC error codes hosted providers use for throttling or overload
+    pub retryable_rpc_codes: Vec<i64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            backoff_multiplier: 2.0,
+            jitter: true,
+            retryable_rpc_codes: vec![429, -32005],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the given retry (1-based).
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let exp = self
+            .backoff_multiplier
+            .powi(retry.saturating_sub(1).min(i32::MAX as u32) as i32);
+        // Compared in seconds first, as the product overflows `Duration` after enough retries
+        let secs = self.initial_backoff.as_secs_f64() * exp;
+        let delay = if secs < self.max_backoff.as_secs_f64() {
+            Duration::from_secs_f64(secs)
+        } else {
+            self.max_backoff
+        };
+        if self.jitter && !delay.is_zero() {
+            rand::thread_rng().gen_range(delay / 2..=delay)
+        } else {
+            delay
+        }
+    }
+
+    /// Network failures and non-JSON bodies (what a throttling proxy or gateway answers with) are
+    /// worth retrying; a well-formed response of the wrong shape is not.
+    pub fn is_retryable_transport_error(&self, error: &HttpTransportError) -> bool {
+        match error {
+            HttpTransportError::Reqwest(_) => true,
+            HttpTransportError::Json(e) => e.is_syntax() || e.is_eof(),
+            HttpTransportError::UnexpectedResponseId(_) => false,
+        }
+    }
+
+    pub fn is_retryable_rpc_error(&self, error: &JsonRpcError) -> bool {
+        self.retryable_rpc_codes.contains(&error.code)
+    }
+}
+
+/// Client-side token bucket. Cloning shares the bucket, so one limiter can cap the combined
+/// request rate of several transports.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+#[derive(Debug)]
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        assert!(requests_per_second > 0.0, "Rate limit must be positive");
+        let capacity = burst.max(1) as f64;
+        Self {
+            bucket: Arc::new(Mutex::new(TokenBucket {
+                capacity,
+                tokens: capacity,
+                refill_per_sec: requests_per_second,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    /// Waits until `cost` requests may be sent and returns how long that took, zero when the
+    /// bucket had enough tokens.
+    pub async fn acquire(&self, cost: u32) -> Duration {
+        let mut started = None;
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap();
+                let now = Instant::now();
+                let refill =
+                    now.duration_since(bucket.last_refill).as_secs_f64() * bucket.refill_per_sec;
+                bucket.tokens = (bucket.tokens + refill).min(bucket.capacity);
+                bucket.last_refill = now;
+
+                // A batch larger than the bucket could never be admitted otherwise
+                let cost = (cost as f64).min(bucket.capacity);
+                if bucket.tokens >= cost {
+                    bucket.tokens -= cost;
+                    return started.map_or(Duration::ZERO, |t: Instant| t.elapsed());
+                }
+                Duration::from_secs_f64((cost - bucket.tokens) / bucket.refill_per_sec)
+            };
+            started.get_or_insert_with(Instant::now);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RpcMetrics {
+    requests: AtomicU64,
+    retries: AtomicU64,
+    failovers: AtomicU64,
+    throttled: AtomicU64,
+    throttled_wait_ms: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RpcMetricsSnapshot {
+    /// HTTP requests sent, batches counting once
+    pub requests: u64,
+    /// Extra rounds over the endpoints after a retryable failure
+    pub retries: u64,
+    /// Requests moved on to another endpoint after a failure
+    pub failovers: u64,
+    /// Requests the rate limiter held back
+    pub throttled: u64,
+    pub throttled_wait: Duration,
+}
+
+impl RpcMetrics {
+    pub fn snapshot(&self) -> RpcMetricsSnapshot {
+        RpcMetricsSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            failovers: self.failovers.load(Ordering::Relaxed),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            throttled_wait: Duration::from_millis(self.throttled_wait_ms.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_retry(&self) {
+        self.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_failover(&self) {
+        self.failovers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_throttle(&self, waited: Duration) {
+        if !waited.is_zero() {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+            self.throttled_wait_ms
+                .fetch_add(waited.as_millis() as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[test]
+fn test_backoff_grows_and_caps() {
+    let policy = RetryPolicy {
+        jitter: false,
+        ..Default::default()
+    };
+    assert_eq!(policy.backoff(1), Duration::from_millis(200));
+    assert_eq!(policy.backoff(2), Duration::from_millis(400));
+    assert_eq!(policy.backoff(3), Duration::from_millis(800));
+    assert_eq!(policy.backoff(20), Duration::from_secs(5));
+    // Far past the point where the delay overflows `Duration`
+    assert_eq!(policy.backoff(100), Duration::from_secs(5));
+    assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(5));
+
+    let policy = RetryPolicy::default();
+    for retry in 1..6 {
+        let delay = policy.backoff(retry);
+        let ceiling = Duration::from_millis(200 * 2u64.pow(retry - 1)).min(policy.max_backoff);
+        assert!(delay >= ceiling / 2 && delay <= ceiling);
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limiter_throttles_past_burst() {
+    let limiter = RateLimiter::new(50.0, 2);
+    assert_eq!(limiter.acquire(1).await, Duration::ZERO);
+    assert_eq!(limiter.acquire(1).await, Duration::ZERO);
+    // The bucket is empty, the next token takes 20ms to refill
+    assert!(limiter.acquire(1).await >= Duration::from_millis(15));
+    // Oversized batches are clamped to the bucket size instead of waiting forever
+    assert!(limiter.acquire(10).await < Duration::from_millis(200));
+}
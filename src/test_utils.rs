@@ -0,0 +1,327 @@
+use crate::{
+    events::{BurnEvent, EventMeta},
+    types::{BtcAmount, PegContext},
+};
+use futures::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use starknet::core::types::Felt;
+use std::sync::{Arc, Mutex};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::broadcast,
+};
+use tokio_tungstenite::tungstenite::Message;
+
+/// The bridge contract the fixtures were taken from.
+pub(crate) const BRIDGE_ADDRESS: &str =
+    "0x37f3357511947cc872aad08b97c49986b90479053630bffb8eeb968b757d255";
+
+/// Where a bridge event in block `block_number` was emitted; the block hash is the number.
+pub(crate) fn test_meta(tx_hash: u64, block_number: u64, event_index: u64) -> EventMeta {
+    EventMeta {
+        from_address: Felt::from_hex(BRIDGE_ADDRESS).unwrap(),
+        block_hash: Felt::from(block_number),
+        block_number,
+        block_timestamp: 1748951234,
+        tx_hash: Felt::from(tx_hash),
+        tx_index: None,
+        event_index,
+    }
+}
+
+/// A peg-in of output 0 of a Bitcoin transaction whose bytes are all `0xab`.
+pub(crate) fn test_context(to: &str, amount: u64) -> PegContext {
+    PegContext {
+        to: to.to_string(),
+        amount,
+        block_height: 100,
+        block_header: vec![],
+        bitcoin_tx_hash: [0xab; 32],
+        bitcoin_tx_index: 1,
+        bitcoin_raw_tx: vec![],
+        bitcoin_merkle_proof: vec![],
+        output_index: 0,
+        dest_script_hash: [0; 32],
+    }
+}
+
+pub(crate) fn test_burn(meta: EventMeta, operator_id: u32) -> BurnEvent {
+    BurnEvent {
+        meta,
+        from: "0x72b1".to_string(),
+        btc_addr: "bcrt1phcnl4zcl2fu047pv4wx6y058v8u0n02at6lthvm7pcf2wrvjm5tqatn90k".to_string(),
+        value: BtcAmount::from_sats(200000),
+        fee_rate: 5,
+        operator_id,
+    }
+}
+
+/// The responses recorded in `tests/fixtures/rpc_v0_8.json`.
+pub(crate) fn rpc_fixture() -> Value {
+    serde_json::from_str(include_str!("../tests/fixtures/rpc_v0_8.json")).unwrap()
+}
+
+/// Block `n` of a chain where every block's hash is its number, holding the fixture
+/// transactions.
+pub(crate) fn chain_block(fixture: &Value, n: u64) -> Value {
+    json!({
+        "block_hash": format!("0x{n:x}"),
+        "parent_hash": format!("0x{:x}", n - 1),
+        "block_number": n,
+        "timestamp": fixture["block"]["timestamp"],
+        "transactions": fixture["block"]["transactions"],
+    })
+}
+
+/// The fixture Mint and Burn events, placed in the block with the given hash.
+pub(crate) fn fixture_events(fixture: &Value, block_hash: &str) -> Value {
+    let mut page = fixture["events"].clone();
+    for event in page["events"].as_array_mut().unwrap() {
+        event["block_hash"] = block_hash.into();
+    }
+    page
+}
+
+/// A receipt of the transaction `params` asks for, emitting its fixture events.
+fn fixture_receipt(fixture: &Value, params: &Value) -> Value {
+    receipt_of(&fixture["events"]["events"], params)
+}
+
+/// A receipt of the transaction `params` asks for, emitting those of `events` it emitted.
+pub(crate) fn receipt_of(events: &Value, params: &Value) -> Value {
+    let tx_hash = &params["transaction_hash"];
+    let emitted: Vec<_> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|e| e["transaction_hash"] == *tx_hash)
+        .map(|e| json!({"from_address": e["from_address"], "keys": e["keys"], "data": e["data"]}))
+        .collect();
+    json!({
+        "transaction_hash": tx_hash,
+        "finality_status": "ACCEPTED_ON_L2",
+        "execution_status": "SUCCEEDED",
+        "events": emitted,
+    })
+}
+
+type Handler = dyn Fn(&str, &Value) -> Result<Value, Value> + Send + Sync;
+
+/// Minimal HTTP JSON-RPC server answering every call through `handler`, which returns either the
+/// `result` or the `error` object of the response. An `Err(Value::Null)` closes the connection
+/// without answering, like a node whose response is lost.
+pub(crate) struct MockRpc {
+    pub url: String,
+    pub calls: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockRpc {
+    pub async fn start(
+        handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let calls = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let server_calls = calls.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, handler.clone(), server_calls.clone()));
+            }
+        });
+
+        Self { url, calls }
+    }
+
+    /// A v0.8 node at block 890900 serving `fixture` on a chain built by [`chain_block`], with
+    /// the fixture events in block 890870. `overrides` is asked first; methods it returns `None`
+    /// for get the fixture's answer.
+    pub async fn from_fixture(
+        fixture: &Value,
+        overrides: impl Fn(&str, &Value) -> Option<Result<Value, Value>> + Send + Sync + 'static,
+    ) -> Self {
+        let fixture = fixture.clone();
+        Self::start(move |method, params| {
+            if let Some(response) = overrides(method, params) {
+                return response;
+            }
+            match method {
+                "starknet_specVersion" => Ok(fixture["spec_version"].clone()),
+                "starknet_blockNumber" => Ok(json!(890900)),
+                "starknet_getEvents" => {
+                    let filter = &params["filter"];
+                    let from = filter["from_block"]["block_number"].as_u64();
+                    let to = filter["to_block"]["block_number"].as_u64();
+                    let in_range = match (from, to) {
+                        (Some(from), Some(to)) => (from..=to).contains(&890870),
+                        _ => true,
+                    };
+                    if filter["continuation_token"].is_string() || !in_range {
+                        Ok(json!({"events": []}))
+                    } else {
+                        Ok(fixture_events(&fixture, "0xd97f6"))
+                    }
+                }
+                "starknet_getBlockWithTxHashes" => {
+                    let n = params["block_id"]["block_number"].as_u64().unwrap();
+                    Ok(chain_block(&fixture, n))
+                }
+                "starknet_getTransactionReceipt" => Ok(fixture_receipt(&fixture, params)),
+                _ => Err(json!({"code": -32601, "message": "Method not found"})),
+            }
+        })
+        .await
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+/// WebSocket counterpart of [`MockRpc`]. Every message sent through `notify` is pushed to all
+/// open connections, `Value::Null` closes them instead.
+pub(crate) struct MockWs {
+    pub url: String,
+    pub calls: Arc<Mutex<Vec<String>>>,
+    pub notify: broadcast::Sender<Value>,
+}
+
+impl MockWs {
+    pub async fn start(
+        handler: impl Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let calls = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+        let (notify, _) = broadcast::channel(16);
+
+        let server_calls = calls.clone();
+        let server_notify = notify.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_ws(
+                    stream,
+                    handler.clone(),
+                    server_calls.clone(),
+                    server_notify.subscribe(),
+                ));
+            }
+        });
+
+        Self { url, calls, notify }
+    }
+
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+async fn serve_ws(
+    stream: TcpStream,
+    handler: Arc<Handler>,
+    calls: Arc<Mutex<Vec<String>>>,
+    mut notifications: broadcast::Receiver<Value>,
+) {
+    let Ok(mut ws) = tokio_tungstenite::accept_async(stream).await else {
+        return;
+    };
+    loop {
+        tokio::select! {
+            message = ws.next() => {
+                let Some(Ok(Message::Text(text))) = message else {
+                    return;
+                };
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let method = request["method"].as_str().unwrap_or_default();
+                calls.lock().unwrap().push(method.to_string());
+                let response = match handler(method, &request["params"]) {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": request["id"], "result": result}),
+                    Err(error) => json!({"jsonrpc": "2.0", "id": request["id"], "error": error}),
+                };
+                if ws.send(Message::text(response.to_string())).await.is_err() {
+                    return;
+                }
+            }
+            notification = notifications.recv() => match notification {
+                Ok(Value::Null) | Err(_) => {
+                    let _ = ws.close(None).await;
+                    return;
+                }
+                Ok(notification) => {
+                    if ws.send(Message::text(notification.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// An address nothing listens on, for simulating an endpoint that is down.
+pub(crate) fn dead_url() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    format!("http://{}", listener.local_addr().unwrap())
+}
+
+async fn serve(mut stream: TcpStream, handler: Arc<Handler>, calls: Arc<Mutex<Vec<String>>>) {
+    let mut buf = vec![];
+    let mut chunk = [0u8; 4096];
+    loop {
+        let Ok(n) = stream.read(&mut chunk).await else {
+            return;
+        };
+        if n == 0 {
+            return;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        let Some(header_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let headers = String::from_utf8_lossy(&buf[..header_end]).to_lowercase();
+        let content_length = headers
+            .lines()
+            .find_map(|l| l.strip_prefix("content-length:"))
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        let body_start = header_end + 4;
+        if buf.len() < body_start + content_length {
+            continue;
+        }
+
+        let request: Value =
+            serde_json::from_slice(&buf[body_start..body_start + content_length]).unwrap();
+        buf.drain(..body_start + content_length);
+
+        let respond = |request: &Value| {
+            let method = request["method"].as_str().unwrap_or_default();
+            calls.lock().unwrap().push(method.to_string());
+            match handler(method, &request["params"]) {
+                Ok(result) => {
+                    Some(json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+                }
+                Err(Value::Null) => None,
+                Err(error) => Some(json!({"jsonrpc": "2.0", "id": request["id"], "error": error})),
+            }
+        };
+        let response = match &request {
+            Value::Array(requests) => requests.iter().map(respond).collect::<Option<_>>(),
+            request => respond(request),
+        };
+        let Some(response) = response else {
+            return;
+        };
+
+        let body = response.to_string();
+        let reply = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        if stream.write_all(reply.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
@@ -1,62 +1,130 @@
+use crate::bindings::bitvm_bridge;
+pub use crate::bindings::bitvm_bridge::{BtcTxProof, Peg};
 use crypto_bigint::Encoding;
-use serde::{Deserialize, Serialize};
-use starknet::core::{
-    codec::{Decode, Encode},
-    types::{ByteArray, Felt, U256},
-};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use starknet::core::types::{Felt, U256};
+use std::fmt;
 
 #[allow(dead_code)]
 pub use starknet::core::types::{
     ExecutionResult, Felt as StarknetAddress, Transaction, TransactionExecutionStatus,
-    TransactionStatus,
 };
 
-// starknet_keccak("mint")
-pub const MINT_FUNCTION_SELECTOR: Felt =
-    Felt::from_hex_unchecked("0x2f0b3c5710379609eb5495f1ecd348cb28167711b73609fe565a72734550354");
+// Generated from the bridge ABI
+pub const MINT_FUNCTION_SELECTOR: Felt = bitvm_bridge::MINT_SELECTOR;
+pub const BURN_FUNCTION_SELECTOR: Felt = bitvm_bridge::BURN_SELECTOR;
+pub const MINT_EVENT_SELECTOR: Felt = bitvm_bridge::events::Mint::SELECTOR;
+pub const BURN_EVENT_SELECTOR: Felt = bitvm_bridge::events::Burn::SELECTOR;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum TransactionEvent {
+    Mint(MintEventData),
+    Burn(BurnEventData),
+    /// An event of another watched contract, as decoded by its decoder
+    Contract(ContractEvent),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ContractEvent {
+    pub name: String,
+    pub fields: serde_json::Value,
+}
+
+/// An amount of satoshis. Wider than the contract's `u64` so that sums over many events (and
+/// `u256` supplies that fit) are never truncated; arithmetic is checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BtcAmount(pub u128);
 
-// starknet_keccak("burn")
-pub const BURN_FUNCTION_SELECTOR: Felt =
-    Felt::from_hex_unchecked("0x3e8cfd4725c1e28fa4a6e3e468b4fcf75367166b850ac5f04e33ec843e82c1");
+impl BtcAmount {
+    pub const ZERO: Self = Self(0);
 
-// starknet_keccak("Mint")
-pub const MINT_EVENT_SELECTOR: Felt =
-    Felt::from_hex_unchecked("0x34e55c1cd55f1338241b50d352f0e91c7e4ffad0e4271d64eb347589ebdfd16");
+    pub fn from_sats(sats: u64) -> Self {
+        Self(sats as u128)
+    }
 
-// starknet_keccak("Burn")
-pub const BURN_EVENT_SELECTOR: Felt =
-    Felt::from_hex_unchecked("0x243e1de00e8a6bc1dfa3e950e6ade24c52e4a25de4dee7fb5affe918ad1e744");
+    pub fn sats(self) -> u128 {
+        self.0
+    }
 
-// starknet_keccak("BalanceIncreased")
-pub const TEST_EVENT_SELECTOR: Felt =
-    Felt::from_hex_unchecked("0x31f8daa2ac8dacd06ab968bad8f97f98f63c30a86dbfcebdd7625f589d4e7e6");
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
 
-// TODO: this is only for test
-#[derive(Debug, PartialEq, Eq, Decode)]
-pub struct TestEventDataWithoutKey {
-    pub bob: ByteArray,
-    pub amount: u32,
+    /// Narrows to the contract's `u64`, failing instead of truncating.
+    pub fn to_u64(self) -> anyhow::Result<u64> {
+        u64::try_from(self.0).map_err(|_| anyhow::anyhow!("Amount {self} exceeds u64"))
+    }
 }
 
-#[derive(Debug)]
-pub enum TransactionEvent {
-    Mint(MintEventData),
-    Burn(BurnEventData),
-    Test(TestEventData), // TODO: this is only for test
+impl From<u64> for BtcAmount {
+    fn from(sats: u64) -> Self {
+        Self::from_sats(sats)
+    }
+}
+
+impl TryFrom<U256> for BtcAmount {
+    type Error = anyhow::Error;
+
+    fn try_from(value: U256) -> Result<Self, Self::Error> {
+        if value.high() != 0 {
+            anyhow::bail!("Amount {value} exceeds u128");
+        }
+        Ok(Self(value.low()))
+    }
+}
+
+impl fmt::Display for BtcAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// A JSON number while it fits u64 (what JSON consumers handle), a decimal string beyond
+impl Serialize for BtcAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match u64::try_from(self.0) {
+            Ok(sats) => serializer.serialize_u64(sats),
+            Err(_) => serializer.serialize_str(&self.0.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BtcAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = BtcAmount;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an unsigned amount as a number or decimal string")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<BtcAmount, E> {
+                Ok(BtcAmount::from_sats(v))
+            }
+
+            fn visit_u128<E: de::Error>(self, v: u128) -> Result<BtcAmount, E> {
+                Ok(BtcAmount(v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<BtcAmount, E> {
+                v.parse().map(BtcAmount).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct MintEventData {
     pub to: String,
-    pub value: u64,
-}
-
-#[derive(Debug, PartialEq, Eq, Decode)]
-pub struct BurnEventDataWithoutKey {
-    pub btc_addr: ByteArray,
-    pub fee_rate: u32,
-    pub value: u64,
-    pub operator_id: u32,
+    pub value: BtcAmount,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -64,28 +132,78 @@ pub struct BurnEventData {
     pub from: String,
     pub btc_addr: String,
     pub fee_rate: u32,
-    pub value: u64,
+    pub value: BtcAmount,
     pub operator_id: u32,
 }
 
-impl BurnEventData {
-    pub fn from_without_key(from: String, data: &BurnEventDataWithoutKey) -> Self {
-        Self {
-            from,
-            btc_addr: String::try_from(data.btc_addr.clone()).unwrap(),
-            fee_rate: data.fee_rate,
-            value: data.value,
-            operator_id: data.operator_id,
-        }
+impl TryFrom<bitvm_bridge::events::Burn> for BurnEventData {
+    type Error = anyhow::Error;
+
+    fn try_from(event: bitvm_bridge::events::Burn) -> Result<Self, Self::Error> {
+        Ok(Self {
+            from: format!("0x{:x}", event.from),
+            btc_addr: String::try_from(event.btc_addr)
+                .map_err(|e| anyhow::anyhow!("Invalid BTC address: {e}"))?,
+            fee_rate: event.fee_rate,
+            value: event.value.into(),
+            operator_id: event.operator_id,
+        })
     }
 }
 
-// TODO: this is only for test, remove this after mint & burn is ready
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct TestEventData {
-    pub alice: String,
-    pub bob: String,
-    pub value: u32,
+/// Finality status as reported by `starknet_getTransactionStatus`. Unlike the provider's own
+/// status type this keeps every spec value, so L1 finality is never folded into L2 acceptance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FinalityStatus {
+    Received,
+    Rejected,
+    Candidate,
+    PreConfirmed,
+    AcceptedOnL2,
+    AcceptedOnL1,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ExecutionStatus {
+    Succeeded,
+    Reverted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TransactionStatus {
+    pub finality_status: FinalityStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution_status: Option<ExecutionStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failure_reason: Option<String>,
+}
+
+impl TransactionStatus {
+    pub fn is_accepted_on_l1(&self) -> bool {
+        self.finality_status == FinalityStatus::AcceptedOnL1
+    }
+
+    /// Accepted on L2 or L1, regardless of execution outcome.
+    pub fn is_accepted(&self) -> bool {
+        matches!(
+            self.finality_status,
+            FinalityStatus::AcceptedOnL2 | FinalityStatus::AcceptedOnL1
+        )
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.finality_status == FinalityStatus::Rejected
+    }
+
+    pub fn is_reverted(&self) -> bool {
+        self.execution_status == Some(ExecutionStatus::Reverted)
+    }
+
+    pub fn is_succeeded(&self) -> bool {
+        self.execution_status == Some(ExecutionStatus::Succeeded)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -102,25 +220,6 @@ pub struct PegContext {
     pub dest_script_hash: [u8; 32],
 }
 
-#[derive(Debug, Encode)]
-pub(crate) struct BtcTxProof {
-    pub block_header: Vec<u8>,
-    pub tx_id: U256,
-    pub tx_index: u32,
-    pub merkle_proof: Vec<U256>,
-    pub raw_tx: Vec<u8>,
-}
-
-#[derive(Debug, Encode)]
-pub(crate) struct Peg {
-    pub to: Felt,
-    pub value: u64,
-    pub block_num: u32,
-    pub inclusion_proof: BtcTxProof,
-    pub tx_out_ix: u32,
-    pub dest_script_hash: U256,
-}
-
 impl TryFrom<PegContext> for Peg {
     type Error = anyhow::Error;
 
@@ -145,7 +244,8 @@ impl TryFrom<PegContext> for Peg {
         Ok(Peg {
             to,
             value: ctx.amount,
-            block_num: ctx.block_height as u32,
+            block_num: u32::try_from(ctx.block_height)
+                .map_err(|_| anyhow::anyhow!("Block height {} exceeds u32", ctx.block_height))?,
             inclusion_proof,
             tx_out_ix: ctx.output_index,
             dest_script_hash: U256::from(crypto_bigint::U256::from_be_slice(&ctx.dest_script_hash)),
@@ -153,11 +253,68 @@ impl TryFrom<PegContext> for Peg {
     }
 }
 
-impl Peg {
-    #[allow(dead_code)]
-    pub fn to_calldata(&self) -> anyhow::Result<Vec<Felt>> {
-        let mut encoded = vec![];
-        self.encode(&mut encoded)?;
-        Ok(encoded)
+#[test]
+fn test_transaction_status_keeps_finality() {
+    let status: TransactionStatus = serde_json::from_str(
+        r#"{"finality_status":"ACCEPTED_ON_L1","execution_status":"SUCCEEDED"}"#,
+    )
+    .unwrap();
+    assert!(status.is_accepted_on_l1());
+    assert!(status.is_accepted());
+    assert!(status.is_succeeded());
+
+    let status: TransactionStatus = serde_json::from_str(
+        r#"{"finality_status":"ACCEPTED_ON_L2","execution_status":"REVERTED","failure_reason":"Insufficient balance"}"#,
+    )
+    .unwrap();
+    assert!(!status.is_accepted_on_l1());
+    assert!(status.is_reverted());
+    assert_eq!(
+        status.failure_reason.as_deref(),
+        Some("Insufficient balance")
+    );
+
+    for (raw, expected) in [
+        ("RECEIVED", FinalityStatus::Received),
+        ("CANDIDATE", FinalityStatus::Candidate),
+        ("PRE_CONFIRMED", FinalityStatus::PreConfirmed),
+        ("REJECTED", FinalityStatus::Rejected),
+    ] {
+        let status: TransactionStatus =
+            serde_json::from_str(&format!(r#"{{"finality_status":"{raw}"}}"#)).unwrap();
+        assert_eq!(status.finality_status, expected);
+        assert!(!status.is_accepted());
     }
+
+    // Unknown statuses must surface as errors rather than be guessed
+    assert!(serde_json::from_str::<TransactionStatus>(r#"{"finality_status":"PENDING"}"#).is_err());
+}
+
+#[test]
+fn test_btc_amount_is_checked_and_lossless() {
+    let max = BtcAmount(u128::MAX);
+    assert_eq!(max.checked_add(BtcAmount::from_sats(1)), None);
+    assert_eq!(BtcAmount::ZERO.checked_sub(BtcAmount::from_sats(1)), None);
+    assert!(max.to_u64().is_err());
+    assert_eq!(BtcAmount::from_sats(u64::MAX).to_u64().unwrap(), u64::MAX);
+
+    // The high word of a u256 must be zero
+    assert_eq!(
+        BtcAmount::try_from(U256::from_words(u128::MAX, 0)).unwrap(),
+        max
+    );
+    assert!(BtcAmount::try_from(U256::from_words(0, 1)).is_err());
+
+    assert_eq!(
+        serde_json::to_string(&BtcAmount::from_sats(500000)).unwrap(),
+        "500000"
+    );
+    let json = serde_json::to_string(&max).unwrap();
+    assert_eq!(json, format!("\"{}\"", u128::MAX));
+    assert_eq!(serde_json::from_str::<BtcAmount>(&json).unwrap(), max);
+    assert_eq!(
+        serde_json::from_str::<BtcAmount>("500000").unwrap(),
+        BtcAmount::from_sats(500000)
+    );
+    assert!(serde_json::from_str::<BtcAmount>("-1").is_err());
 }
@@ -1,17 +1,68 @@
-use crate::types::{
-    BURN_EVENT_SELECTOR, BurnEventData, BurnEventDataWithoutKey, MINT_EVENT_SELECTOR,
-    MintEventData, TEST_EVENT_SELECTOR, TestEventData, TestEventDataWithoutKey, TransactionEvent,
-};
+use crate::bindings::bitvm_bridge::events::Burn;
+use crate::spec::EmittedEvent;
+use crate::types::{BURN_EVENT_SELECTOR, MINT_EVENT_SELECTOR, MintEventData, TransactionEvent};
 use starknet::core::{
     codec::Decode,
-    types::{EmittedEvent, Felt, MaybePendingBlockWithTxHashes},
+    types::{Felt, MaybePendingBlockWithTxHashes, U256},
 };
+use std::{fs, io::Write, path::Path};
 
-// Make sure the felt is within u64 range
+/// Fails if the felt does not fit a u64 rather than truncating it.
 pub fn felt_to_u64(f: &Felt) -> anyhow::Result<u64> {
-    let bytes = f.to_bytes_be();
-    let value = u64::from_be_bytes(bytes[24..32].try_into()?);
-    Ok(value)
+    Ok(u64::decode(std::slice::from_ref(f))?)
+}
+
+/// Fails if the felt does not fit a u128 rather than truncating it.
+pub fn felt_to_u128(f: &Felt) -> anyhow::Result<u128> {
+    Ok(u128::decode(std::slice::from_ref(f))?)
+}
+
+/// Decodes a Cairo `u256`, serialized as its low then high 128-bit words.
+pub fn felts_to_u256(felts: &[Felt]) -> anyhow::Result<U256> {
+    match felts {
+        [low, high] => Ok(U256::from_words(felt_to_u128(low)?, felt_to_u128(high)?)),
+        _ => anyhow::bail!("Expected 2 felts for a u256, got {}", felts.len()),
+    }
+}
+
+/// A Bitcoin transaction hash in the byte order of [`crate::types::PegContext`] as the txid
+/// Bitcoin tools display, i.e. reversed.
+pub fn btc_txid(tx_hash: &[u8; 32]) -> String {
+    let mut bytes = *tx_hash;
+    bytes.reverse();
+    hex::encode(bytes)
+}
+
+/// Replaces the file at `path` with `contents` so that a crash leaves either the old or the new
+/// file, and the new one is on disk once this returns.
+pub(crate) fn replace_file(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    // The rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Runs `f` with the connection on a blocking thread, so SQLite I/O does not stall the runtime.
+#[cfg(feature = "sqlite")]
+pub(crate) async fn with_connection<T: Send + 'static>(
+    conn: &std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>,
+    f: impl FnOnce(&mut rusqlite::Connection) -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    let conn = conn.clone();
+    tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
 }
 
 // First key is the selector, if second key is exists, it is the key(indexer) of the event data
@@ -21,30 +72,27 @@ pub fn parse_event(event: &EmittedEvent) -> anyhow::Result<TransactionEvent> {
         .first()
         .ok_or(anyhow::anyhow!("Invalid event keys"))?;
 
-    if *key == TEST_EVENT_SELECTOR {
-        let alice = format!("{:x}", &event.keys[1]);
-        let other = TestEventDataWithoutKey::decode(&event.data)?;
-        let bob = String::try_from(other.bob)?;
-        Ok(TransactionEvent::Test(TestEventData {
-            alice,
-            bob,
-            value: other.amount,
-        }))
-    } else if *key == MINT_EVENT_SELECTOR {
-        let to = format!("0x{:x}", &event.keys[1]);
+    if *key == MINT_EVENT_SELECTOR {
+        // Read as the client always has, the recipient key and the last data felt, rather than
+        // by the generated decoder: the ABI is not the deployed class's (see `abi/README.md`),
+        // and the Mint may carry more data than it declares
+        let to = event
+            .keys
+            .get(1)
+            .ok_or(anyhow::anyhow!("Invalid event keys"))?;
         let value = felt_to_u64(
             event
                 .data
                 .last()
                 .ok_or(anyhow::anyhow!("Invalid event data"))?,
         )?;
-        Ok(TransactionEvent::Mint(MintEventData { to, value }))
+        Ok(TransactionEvent::Mint(MintEventData {
+            to: format!("0x{to:x}"),
+            value: value.into(),
+        }))
     } else if *key == BURN_EVENT_SELECTOR {
-        let from = format!("0x{:x}", &event.keys[1]);
-        let other = BurnEventDataWithoutKey::decode(&event.data)?;
-        Ok(TransactionEvent::Burn(BurnEventData::from_without_key(
-            from, &other,
-        )))
+        let burn = Burn::decode(&event.keys, &event.data)?;
+        Ok(TransactionEvent::Burn(burn.try_into()?))
     } else {
         anyhow::bail!("Unspported event type")
     }
@@ -61,24 +109,39 @@ pub fn block_timestamp(block: &MaybePendingBlockWithTxHashes) -> u64 {
 fn test_event_keys() {
     use starknet::core::utils::get_selector_from_name;
     let selector = get_selector_from_name("burn").unwrap();
-    println!("selector: {:?}", selector);
+    println!("selector: {selector:?}");
 }
 
 #[test]
-fn test_data() {
-    let data = vec![
-        Felt::from_hex("0x2").unwrap(),
-        Felt::from_hex("0x62633170356437726a7137673672646b3279687a6b7339736d6c6171746564").unwrap(),
-        Felt::from_hex("0x723464656b7130386765387a74776163373273667239727573786733323937").unwrap(),
-        Felt::from_hex("0x0").unwrap(),
-        Felt::from_hex("0x0").unwrap(),
-        Felt::from_hex("0x64").unwrap(),
-    ];
+fn test_felt_conversions_are_checked() {
+    assert_eq!(felt_to_u64(&Felt::from(u64::MAX)).unwrap(), u64::MAX);
+    // Used to come back as the low 8 bytes
+    assert!(felt_to_u64(&Felt::from(u64::MAX as u128 + 1)).is_err());
+    assert_eq!(felt_to_u128(&Felt::from(u128::MAX)).unwrap(), u128::MAX);
+    assert!(felt_to_u128(&(Felt::from(u128::MAX) + Felt::ONE)).is_err());
+
+    let value = felts_to_u256(&[Felt::from(5u8), Felt::ONE]).unwrap();
+    assert_eq!((value.low(), value.high()), (5, 1));
+    assert!(felts_to_u256(&[Felt::from(5u8)]).is_err());
+    assert!(felts_to_u256(&[Felt::MAX, Felt::ZERO]).is_err());
+
+    // A Mint value that does not fit the contract's u64 is rejected, not truncated
+    let event: EmittedEvent = serde_json::from_value(serde_json::json!({
+        "from_address": "0x1",
+        "keys": [MINT_EVENT_SELECTOR, "0x2"],
+        "data": [Felt::from(u64::MAX as u128 + 500000)],
+        "block_hash": "0x3",
+        "block_number": 1,
+        "transaction_hash": "0x4",
+    }))
+    .unwrap();
+    assert!(parse_event(&event).is_err());
 
-    let result = TestEventDataWithoutKey::decode(&data).unwrap();
-    println!(
-        "bob: {}, amount: {}",
-        String::try_from(result.bob).unwrap(),
-        result.amount
-    );
+    // Data ahead of the amount is tolerated
+    let mut event = event;
+    event.data = vec![Felt::from(7u8), Felt::from(500000u64)];
+    let TransactionEvent::Mint(mint) = parse_event(&event).unwrap() else {
+        panic!("Expected a mint");
+    };
+    assert_eq!((mint.to.as_str(), mint.value), ("0x2", 500000.into()));
 }
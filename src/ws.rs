@@ -0,0 +1,150 @@
+use crate::spec::{BlockHeader, EmittedEvent};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use starknet::core::types::Felt;
+use std::collections::VecDeque;
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, tungstenite::Message};
+
+/// A message pushed by the node for one of the client's subscriptions.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    NewHead(BlockHeader),
+    Event(EmittedEvent),
+    /// Blocks in this range were replaced; notifications for the new chain follow
+    Reorg {
+        starting_block_number: u64,
+        ending_block_number: u64,
+    },
+}
+
+/// Starknet JSON-RPC client for the WebSocket subscription methods (spec v0.8+).
+pub struct WsClient {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+    // Notifications that arrived while waiting for a response
+    notifications: VecDeque<Notification>,
+}
+
+impl WsClient {
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let (stream, _) = tokio_tungstenite::connect_async(url)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to connect to {url}: {e}"))?;
+        Ok(Self {
+            stream,
+            next_id: 0,
+            notifications: VecDeque::new(),
+        })
+    }
+
+    /// Subscribes to new block headers and returns the subscription id.
+    pub async fn subscribe_new_heads(&mut self) -> anyhow::Result<String> {
+        let id = self
+            .request("starknet_subscribeNewHeads", json!({}))
+            .await?;
+        subscription_id(id)
+    }
+
+    /// Subscribes to the events of `from_address` matching `keys`, replaying those of blocks
+    /// since `from_block` when given (nodes limit how far back that may be).
+    pub async fn subscribe_events(
+        &mut self,
+        from_address: Felt,
+        keys: Vec<Vec<Felt>>,
+        from_block: Option<u64>,
+    ) -> anyhow::Result<String> {
+        let mut params = json!({ "from_address": from_address, "keys": keys });
+        if let Some(block_number) = from_block {
+            params["block_id"] = json!({ "block_number": block_number });
+        }
+        let id = self.request("starknet_subscribeEvents", params).await?;
+        subscription_id(id)
+    }
+
+    /// Waits for the next notification. Fails once the connection is lost.
+    pub async fn next_notification(&mut self) -> anyhow::Result<Notification> {
+        if let Some(notification) = self.notifications.pop_front() {
+            return Ok(notification);
+        }
+        loop {
+            let message = self.read().await?;
+            if let Some(notification) = parse_notification(&message)? {
+                return Ok(notification);
+            }
+        }
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> anyhow::Result<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        self.stream.send(Message::text(request.to_string())).await?;
+
+        loop {
+            let message = self.read().await?;
+            if message["id"] != id {
+                if let Some(notification) = parse_notification(&message)? {
+                    self.notifications.push_back(notification);
+                }
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                anyhow::bail!("{method} failed: {error}");
+            }
+            return Ok(message["result"].clone());
+        }
+    }
+
+    async fn read(&mut self) -> anyhow::Result<Value> {
+        loop {
+            let message = self
+                .stream
+                .next()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("WebSocket connection closed"))??;
+            match message {
+                Message::Text(text) => return Ok(serde_json::from_str(&text)?),
+                Message::Binary(data) => return Ok(serde_json::from_slice(&data)?),
+                Message::Close(_) => anyhow::bail!("WebSocket connection closed"),
+                // Pings are answered by tungstenite itself
+                _ => {}
+            }
+        }
+    }
+}
+
+fn subscription_id(id: Value) -> anyhow::Result<String> {
+    match id {
+        Value::String(id) => Ok(id),
+        Value::Number(id) => Ok(id.to_string()),
+        id => anyhow::bail!("Invalid subscription id {id}"),
+    }
+}
+
+fn parse_notification(message: &Value) -> anyhow::Result<Option<Notification>> {
+    #[derive(Deserialize)]
+    struct Reorg {
+        starting_block_number: u64,
+        ending_block_number: u64,
+    }
+
+    let result = message["params"]["result"].clone();
+    let notification = match message["method"].as_str() {
+        Some("starknet_subscriptionNewHeads") => {
+            Notification::NewHead(serde_json::from_value(result)?)
+        }
+        Some("starknet_subscriptionEvents") => Notification::Event(serde_json::from_value(result)?),
+        Some("starknet_subscriptionReorg") => {
+            let reorg: Reorg = serde_json::from_value(result)?;
+            Notification::Reorg {
+                starting_block_number: reorg.starting_block_number,
+                ending_block_number: reorg.ending_block_number,
+            }
+        }
+        // Responses to unknown requests and notifications the SDK does not subscribe to
+        _ => return Ok(None),
+    };
+    Ok(Some(notification))
+}